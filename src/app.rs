@@ -1,84 +1,1261 @@
-mod curve;
+pub mod curve;
+mod history;
+mod lane;
+pub mod output;
+mod preset;
+pub mod transport;
 
-use self::curve::Curve;
-use chrono::{NaiveDateTime, Utc};
-use egui::{Checkbox, Slider};
+use self::{
+    curve::{
+        snap::{Snap, SUBDIVISIONS},
+        time_signature::NOTE_VALUES,
+        Clip, Curve, Generator, OutOfRange, ValueRange, View, Waveform,
+    },
+    history::History,
+    lane::Lane,
+    output::{
+        cc::{CcRoute, CcSender, Controller},
+        osc::{ArgumentType, OscRoute, OscSender},
+        Output,
+    },
+    preset::{Preset, PresetForm},
+    transport::{midi, midi::MidiInput, tempo, Transport},
+};
+use chrono::Utc;
+use egui::{Button, Checkbox, ComboBox, DragValue, Key, KeyboardShortcut, Modifiers, Slider};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 
+const UNDO_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+const REDO_SHORTCUT: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+// Only shown in the menu, egui turns them into copy, cut and paste events
+const CUT_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::X);
+const COPY_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::C);
+const PASTE_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::V);
+const SHORTCUTS_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F1);
+const PLAY_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::Space);
+const TAP_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::T);
+const DOWNBEAT_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::D);
+// Held down instead of pressed
+const SLOWER_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::Comma);
+const FASTER_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::Period);
+
+/// Bump whenever the persisted fields change and handle the old version in `TemplateApp::migrate`
+const STATE_VERSION: u32 = 2;
+
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
 pub struct TemplateApp {
+    /// Version of the persisted state, `0` if it is missing
+    #[serde(default)]
+    version: u32,
     show_progress: bool,
-    run: bool,
-    start: NaiveDateTime,
+    /// Only the tempo is saved
+    transport: Transport,
+    /// Position of the playhead, updated from `transport` every frame
     x: f32,
-    curve: Curve,
+    /// Beats the transport loops instead of the whole curves
+    #[serde(skip)]
+    loop_region: Option<(f32, f32)>,
+    /// Raw MIDI device the clock is read from
+    midi_device: String,
+    #[serde(skip)]
+    midi_input: Option<MidiInput>,
+    /// Why the MIDI device could not be opened
+    #[serde(skip)]
+    midi_error: Option<String>,
+    /// Values sent per second by the outputs
+    output_rate: f32,
+    #[serde(skip)]
+    cc_output: Option<Output<CcSender>>,
+    /// `host:port` OSC messages are sent to
+    osc_target: String,
+    #[serde(skip)]
+    osc_output: Option<Output<OscSender>>,
+    /// Why an output stopped or could not be started
+    #[serde(skip)]
+    output_error: Option<String>,
+    /// Never empty
+    lanes: Vec<Lane>,
+    /// Index of the lane shown in the editor
+    selected: usize,
+    /// Single curve of version 1, moved into `lanes` by `migrate`
+    #[serde(skip_serializing)]
+    curve: Option<Curve>,
     edit_mode: bool,
+    snap: Snap,
+    #[serde(skip)]
+    view: View,
+    /// Indices of the selected points of the selected lane
+    #[serde(skip)]
+    selection: BTreeSet<usize>,
+    #[serde(skip)]
+    history: History<Vec<Lane>>,
+    /// Last copied clip, the system clipboard can only be read by pasting with the keyboard
+    #[serde(skip)]
+    clipboard: String,
+    #[serde(skip)]
+    show_shortcuts: bool,
+    /// Settings of the last generated curve
+    generator: Generator,
+    #[serde(skip)]
+    show_generator: bool,
+    presets: Vec<Preset>,
+    /// Pack file presets are imported from and exported to
+    preset_path: String,
+    #[serde(skip)]
+    preset_form: PresetForm,
+    #[serde(skip)]
+    show_presets: bool,
 }
 
 impl Default for TemplateApp {
     fn default() -> Self {
         Self {
+            version: STATE_VERSION,
             edit_mode: false,
             show_progress: false,
-            run: false,
-            start: Utc::now().naive_utc(),
+            transport: Default::default(),
             x: Default::default(),
-            curve: Default::default(),
+            loop_region: None,
+            midi_device: Default::default(),
+            midi_input: None,
+            midi_error: None,
+            output_rate: 100.0,
+            cc_output: None,
+            osc_target: "127.0.0.1:7000".to_owned(),
+            osc_output: None,
+            output_error: None,
+            lanes: vec![Lane::new("Lane 1", Curve::default())],
+            selected: 0,
+            curve: None,
+            snap: Default::default(),
+            view: Default::default(),
+            selection: Default::default(),
+            history: Default::default(),
+            clipboard: Default::default(),
+            show_shortcuts: false,
+            generator: Default::default(),
+            show_generator: false,
+            presets: Vec::new(),
+            preset_path: "presets.json".to_owned(),
+            preset_form: Default::default(),
+            show_presets: false,
         }
     }
 }
 
 impl TemplateApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        cc.storage
+            .and_then(|storage| eframe::get_value::<Self>(storage, eframe::APP_KEY))
+            .and_then(Self::migrate)
+            .map(|app| {
+                // Continues where the playhead was left
+                app.transport.seek(app.x as f64);
+                app
+            })
+            .unwrap_or_default()
+    }
+
+    /// Upgrades state stored by an older version, `None` if it can not be used anymore
+    fn migrate(mut self) -> Option<Self> {
+        match self.version {
+            1 => {
+                self.lanes = vec![Lane::new("Lane 1", self.curve.take()?)];
+                self.selected = 0;
+                self.version = STATE_VERSION;
+                Some(self)
+            }
+            STATE_VERSION if !self.lanes.is_empty() => {
+                self.selected = self.selected.min(self.lanes.len() - 1);
+                Some(self)
+            }
+            version => {
+                log::warn!("Discarding stored state of unknown version {version}");
+                None
+            }
+        }
+    }
+
+    /// Returns whether a nudge button is held, positive for faster
+    fn transport_ui(&mut self, ui: &mut egui::Ui, length: f32) -> i32 {
+        ui.add_enabled_ui(self.show_progress, |ui| {
+            let mut playing = self.transport.is_playing();
+            if ui
+                .checkbox(&mut playing, "Run")
+                .on_hover_text(ui.ctx().format_shortcut(&PLAY_SHORTCUT))
+                .changed()
+            {
+                if playing {
+                    self.transport.play();
+                } else {
+                    self.transport.pause();
+                }
+            }
+            if ui.button("Stop").clicked() {
+                self.transport.stop();
+            }
+            if ui.add(Slider::new(&mut self.x, 0.0f32..=length)).changed() {
+                self.transport.seek(self.x as f64);
+            }
+
+            let range = self.selected_range();
+            let mut looping = self.loop_region.is_some();
+            if ui
+                .add_enabled(
+                    looping || range.is_some(),
+                    egui::SelectableLabel::new(looping, "Loop selection"),
+                )
+                .on_hover_text("Plays the beats between the selected points over and over")
+                .clicked()
+            {
+                looping = !looping;
+                self.loop_region = range.filter(|_| looping);
+            }
+        });
+
+        let mut bpm = self.transport.bpm();
+        let response = ui.add(
+            DragValue::new(&mut bpm)
+                .clamp_range(tempo::MIN_BPM..=tempo::MAX_BPM)
+                .speed(0.1)
+                .max_decimals(1)
+                .suffix(" bpm"),
+        );
+        if response.changed() {
+            self.transport.set_bpm(bpm);
+        }
+        let tap = Button::new("Tap").shortcut_text(ui.ctx().format_shortcut(&TAP_SHORTCUT));
+        if ui.add(tap).clicked() {
+            self.transport.tap();
+        }
+        // Only while held, to line up with other music
+        let slower = ui.button("−").on_hover_text("Hold to play slower");
+        let faster = ui.button("+").on_hover_text("Hold to play faster");
+        if ui
+            .button("Downbeat")
+            .on_hover_text("Restart the loop region now")
+            .clicked()
+        {
+            self.transport.restart();
+        }
+        faster.is_pointer_button_down_on() as i32 - slower.is_pointer_button_down_on() as i32
+    }
+
+    fn sync_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("MIDI clock input");
+        ComboBox::from_id_source("midi_device")
+            .selected_text(&self.midi_device)
+            .show_ui(ui, |ui| {
+                for device in midi::devices() {
+                    let text = device.clone();
+                    ui.selectable_value(&mut self.midi_device, device, text);
+                }
+            });
+        ui.text_edit_singleline(&mut self.midi_device)
+            .on_hover_text("Path of a raw MIDI device, e.g. /dev/snd/midiC1D0 of snd-virmidi");
+
+        match &self.midi_input {
+            Some(input) if input.is_connected() => {
+                let sync = input.sync();
+                let state = if sync.is_running() {
+                    "playing"
+                } else {
+                    "stopped"
+                };
+                match sync.bpm() {
+                    Some(bpm) => {
+                        ui.label(format!("Following {}, {bpm:.1} bpm, {state}", input.path()))
+                    }
+                    None => ui.label(format!("Waiting for clock on {}", input.path())),
+                };
+                if ui.button("Disconnect").clicked() {
+                    self.midi_input = None;
+                }
+            }
+            _ => {
+                if ui
+                    .add_enabled(!self.midi_device.is_empty(), Button::new("Connect"))
+                    .clicked()
+                {
+                    match MidiInput::open(&self.midi_device, self.transport.clone()) {
+                        Ok(input) => {
+                            self.midi_input = Some(input);
+                            self.midi_error = None;
+                        }
+                        Err(err) => self.midi_error = Some(err.to_string()),
+                    }
+                }
+            }
+        }
+        if let Some(error) = &self.midi_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    }
+
+    fn cc_route_ui(ui: &mut egui::Ui, route: &mut Option<CcRoute>) {
+        let mut enabled = route.is_some();
+        ui.checkbox(&mut enabled, "Send values");
+        if !enabled {
+            *route = None;
+            return;
+        }
+        let route = route.get_or_insert_with(Default::default);
+
+        ComboBox::from_id_source("cc_device")
+            .selected_text(&route.device)
+            .show_ui(ui, |ui| {
+                for device in midi::devices() {
+                    let text = device.clone();
+                    ui.selectable_value(&mut route.device, device, text);
+                }
+            });
+        ui.text_edit_singleline(&mut route.device)
+            .on_hover_text("Path of a raw MIDI device, e.g. /dev/snd/midiC1D0 of snd-virmidi");
+
+        let mut channel = route.channel + 1;
+        ui.add(
+            DragValue::new(&mut channel)
+                .clamp_range(1..=16)
+                .prefix("Channel "),
+        );
+        route.channel = channel - 1;
+
+        ui.horizontal(|ui| {
+            let (mut number, nrpn) = match route.controller {
+                Controller::Cc(number) => (number as u16, false),
+                Controller::Nrpn(number) => (number, true),
+            };
+            let mut selected = nrpn;
+            ui.radio_value(&mut selected, false, "CC");
+            ui.radio_value(&mut selected, true, "NRPN")
+                .on_hover_text("14 bit values");
+            let max = if selected { 16383 } else { 127 };
+            ui.add(DragValue::new(&mut number).clamp_range(0..=max));
+            number = number.min(max);
+            route.controller = if selected {
+                Controller::Nrpn(number)
+            } else {
+                Controller::Cc(number as u8)
+            };
+        });
+    }
+
+    fn osc_route_ui(ui: &mut egui::Ui, route: &mut Option<OscRoute>) {
+        let mut enabled = route.is_some();
+        ui.checkbox(&mut enabled, "Send values");
+        if !enabled {
+            *route = None;
+            return;
+        }
+        let route = route.get_or_insert_with(Default::default);
+
+        let address = ui
+            .text_edit_singleline(&mut route.address)
+            .on_hover_text("Address pattern, e.g. /layer1/opacity");
+        if !route.is_valid() {
+            address.on_hover_text("Must start with a slash and contain no spaces");
+            ui.colored_label(ui.visuals().error_fg_color, "Invalid address");
+        }
+        ui.horizontal(|ui| {
+            for argument in ArgumentType::ALL {
+                ui.radio_value(&mut route.argument, argument, argument.to_string());
+            }
+        });
+    }
+
+    fn output_ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            DragValue::new(&mut self.output_rate)
+                .clamp_range(output::MIN_RATE..=output::MAX_RATE)
+                .speed(1.0)
+                .suffix(" values/s"),
+        )
+        .on_hover_text("How often the values are sent during playback");
+
+        let mut sending = self.cc_output.is_some();
+        if ui
+            .checkbox(&mut sending, "Send MIDI CC")
+            .on_hover_text("Routes are set up in the context menu of a lane")
+            .changed()
+        {
+            self.cc_output = None;
+            self.output_error = None;
+            if sending {
+                match Output::start(
+                    CcSender::default(),
+                    self.transport.clone(),
+                    self.output_rate,
+                ) {
+                    Ok(output) => self.cc_output = Some(output),
+                    Err(err) => self.output_error = Some(err.to_string()),
+                }
+            }
+        }
+
+        ui.separator();
+        ui.add_enabled(
+            self.osc_output.is_none(),
+            egui::TextEdit::singleline(&mut self.osc_target),
+        )
+        .on_hover_text("host:port of the OSC receiver");
+        let mut sending = self.osc_output.is_some();
+        if ui
+            .checkbox(&mut sending, "Send OSC")
+            .on_hover_text("Addresses are set up in the context menu of a lane")
+            .changed()
+        {
+            self.osc_output = None;
+            self.output_error = None;
+            if sending {
+                match OscSender::new(&self.osc_target).and_then(|sender| {
+                    Output::start(sender, self.transport.clone(), self.output_rate)
+                }) {
+                    Ok(output) => self.osc_output = Some(output),
+                    Err(err) => self.output_error = Some(err.to_string()),
+                }
+            }
+        }
+        if let Some(error) = &self.output_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    }
+
+    /// Hands the routed curves over to the outputs
+    fn update_outputs(&mut self) {
+        let audible = || {
+            self.lanes
+                .iter()
+                .filter(|lane| lane::is_audible(&self.lanes, lane))
+        };
+
+        if let Some(output) = &self.cc_output {
+            output.set_rate(self.output_rate);
+            output.set_routes(
+                audible()
+                    .filter_map(|lane| Some((lane.curve.clone(), lane.cc.clone()?)))
+                    .filter(|(_, route)| !route.device.is_empty())
+                    .collect(),
+            );
+        }
+        if let Some(output) = &self.osc_output {
+            output.set_rate(self.output_rate);
+            output.set_routes(
+                audible()
+                    .filter_map(|lane| Some((lane.curve.clone(), lane.osc.clone()?)))
+                    .collect(),
+            );
+        }
+
+        if let Some(error) = self.cc_output.as_ref().and_then(Output::error) {
+            self.output_error = Some(error);
+            self.cc_output = None;
+        }
+        if let Some(error) = self.osc_output.as_ref().and_then(Output::error) {
+            self.output_error = Some(error);
+            self.osc_output = None;
+        }
+    }
+
+    fn curve_mut(&mut self) -> &mut Curve {
+        &mut self.lanes[self.selected].curve
+    }
+
+    fn lanes_ui(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        let mut swap = None;
+        let count = self.lanes.len();
+
+        for i in 0..count {
+            let audible = lane::is_audible(&self.lanes, &self.lanes[i]);
+            let lane = &mut self.lanes[i];
+            ui.horizontal(|ui| {
+                let name = ui.selectable_label(self.selected == i, &lane.name);
+                if name.clicked() && self.selected != i {
+                    self.selected = i;
+                    self.selection.clear();
+                }
+                name.context_menu(|ui| {
+                    ui.text_edit_singleline(&mut lane.name);
+                    if ui.add_enabled(i > 0, Button::new("Move up")).clicked() {
+                        swap = Some((i - 1, i));
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(i + 1 < count, Button::new("Move down"))
+                        .clicked()
+                    {
+                        swap = Some((i, i + 1));
+                        ui.close_menu();
+                    }
+                    ui.menu_button("MIDI CC", |ui| Self::cc_route_ui(ui, &mut lane.cc));
+                    ui.menu_button("OSC", |ui| Self::osc_route_ui(ui, &mut lane.osc));
+                    if ui.add_enabled(count > 1, Button::new("Remove")).clicked() {
+                        remove = Some(i);
+                        ui.close_menu();
+                    }
+                });
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.toggle_value(&mut lane.mute, "M").on_hover_text("Mute");
+                    ui.toggle_value(&mut lane.solo, "S").on_hover_text("Solo");
+                    let value = lane.curve.range().format(lane.curve.value(self.x));
+                    if audible {
+                        ui.label(value);
+                    } else {
+                        ui.weak(value);
+                    }
+                });
+            });
+        }
+
+        if ui.button("Add lane").clicked() {
+            self.lanes
+                .push(Lane::new(lane::unused_name(&self.lanes), Curve::default()));
+            self.selected = self.lanes.len() - 1;
+            self.selection.clear();
+        }
+
+        if let Some((a, b)) = swap {
+            self.lanes.swap(a, b);
+            if self.selected == a {
+                self.selected = b;
+            } else if self.selected == b {
+                self.selected = a;
+            }
+        }
+        if let Some(i) = remove {
+            self.lanes.remove(i);
+            if self.selected > i || self.selected == self.lanes.len() {
+                self.selected -= 1;
+            }
+        }
+    }
+
+    fn loop_ui(&mut self, ui: &mut egui::Ui) {
+        let curve = self.curve_mut();
+        let mut time_signature = curve.time_signature();
+        let mut bars = curve.bars();
+
+        ui.add(
+            DragValue::new(&mut bars)
+                .clamp_range(1..=64)
+                .suffix(" bars"),
+        );
+        ui.label("of");
+        ui.add(DragValue::new(&mut time_signature.beats).clamp_range(1..=32));
+        ComboBox::from_id_source("note_value")
+            .width(40.0)
+            .selected_text(format!("/{}", time_signature.note_value))
+            .show_ui(ui, |ui| {
+                for note_value in NOTE_VALUES {
+                    ui.selectable_value(
+                        &mut time_signature.note_value,
+                        note_value,
+                        format!("/{note_value}"),
+                    );
+                }
+            });
+
+        if time_signature != curve.time_signature() || bars != curve.bars() {
+            curve.set_time_signature(time_signature, bars);
+        }
+    }
+
+    /// Beats between the first and the last selected point
+    fn selected_range(&self) -> Option<(f32, f32)> {
+        let curve = &self.lanes[self.selected].curve;
+        let xs = self
+            .selection
+            .iter()
+            .filter_map(|i| curve.position(*i))
+            .map(|pos| pos.x);
+        let (from, to) = xs.fold((f32::INFINITY, f32::NEG_INFINITY), |(from, to), x| {
+            (from.min(x), to.max(x))
+        });
+        Some((from, to)).filter(|(from, to)| from < to)
+    }
+
+    fn copy(&mut self, ctx: &egui::Context, cut: bool) {
+        let Some((from, to)) = self.selected_range() else {
+            return;
+        };
+        let curve = self.curve_mut();
+        let clip = if cut {
+            curve.cut(from, to)
+        } else {
+            curve.copy(from, to)
+        };
+        match clip {
+            Ok(clip) => {
+                self.clipboard = clip.to_text();
+                ctx.output_mut(|output| output.copied_text = self.clipboard.clone());
+                if cut {
+                    self.selection.clear();
+                }
+            }
+            Err(err) => log::warn!("Could not copy: {err}"),
+        }
+    }
+
+    /// Pastes at `beat` or the playhead
+    fn paste(&mut self, text: &str, beat: Option<f32>) {
+        let playhead = self.x;
+        let curve = self.curve_mut();
+        let beat = beat.unwrap_or_else(|| {
+            curve
+                .position_in_loop(playhead, OutOfRange::Wrap)
+                .unwrap_or_default()
+        });
+        let pasted = Clip::from_text(text)
+            .map_err(|err| err.to_string())
+            .and_then(|clip| curve.paste(&clip, beat).map_err(|err| err.to_string()));
+        match pasted {
+            Ok(()) => self.selection.clear(),
+            Err(err) => log::warn!("Could not paste: {err}"),
+        }
+    }
+
+    fn transform_ui(&mut self, ui: &mut egui::Ui) {
+        let grid = 1.0 / self.snap.subdivision.max(1) as f32;
+        let range = self.selected_range();
+        let curve = self.curve_mut();
+        let mut result = None;
+
+        if ui.button("Reverse in time").clicked() {
+            curve.reverse();
+            result = Some(Ok(()));
+        }
+        if ui.button("Invert values").clicked() {
+            curve.invert();
+            result = Some(Ok(()));
+        }
+        ui.separator();
+        for (name, factor) in [("Scale values ×2", 2.0), ("Scale values ×½", 0.5)] {
+            if ui.button(name).clicked() {
+                result = Some(curve.scale_values(factor));
+            }
+        }
+        for (name, offset) in [("Raise values by 10%", 0.1), ("Lower values by 10%", -0.1)] {
+            if ui.button(name).clicked() {
+                result = Some(curve.offset_values(offset));
+            }
+        }
+        ui.separator();
+        for (name, beats) in [
+            ("Shift a beat later", 1.0),
+            ("Shift a beat earlier", -1.0),
+            ("Shift a grid step later", grid),
+            ("Shift a grid step earlier", -grid),
+        ] {
+            if ui.button(name).clicked() {
+                result = Some(curve.shift(beats));
+            }
+        }
+        ui.separator();
+        for (name, factor) in [
+            ("Stretch selection ×2", 2.0),
+            ("Compress selection ×½", 0.5),
+        ] {
+            let button = ui
+                .add_enabled(range.is_some(), Button::new(name))
+                .on_hover_text("The rest of the loop makes room")
+                .on_disabled_hover_text("Select points to stretch the beats between them");
+            if let (true, Some((from, to))) = (button.clicked(), range) {
+                result = Some(curve.stretch_range(from, to, factor));
+            }
+        }
+
+        if let Some(result) = result {
+            if let Err(err) = result {
+                log::warn!("Could not transform the curve: {err}");
+            }
+            // Splitting segments changes the indices of the points
+            self.selection.clear();
+            ui.close_menu();
+        }
+    }
+
+    fn inspector_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Point");
+        let index = match self.selection.len() {
+            0 => {
+                ui.weak("Select a point to edit its position");
+                return;
+            }
+            1 => *self.selection.first().expect("One point is selected"),
+            count => {
+                ui.weak(format!("{count} points selected"));
+                return;
+            }
+        };
+        if let Some(edit) = self.lanes[self.selected].curve.inspector_ui(ui, index) {
+            if let Err(err) = self.curve_mut().apply(&edit) {
+                log::warn!("Could not apply {edit:?}: {err}");
+            }
+        }
+    }
+
+    fn presets_ui(&mut self, ui: &mut egui::Ui) {
+        let form = &mut self.preset_form;
+        egui::Grid::new("new_preset").num_columns(2).show(ui, |ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut form.name);
+            ui.end_row();
+            ui.label("Folder");
+            ui.text_edit_singleline(&mut form.folder);
+            ui.end_row();
+            ui.label("Tags");
+            ui.add(egui::TextEdit::singleline(&mut form.tags).hint_text("Comma separated"));
+            ui.end_row();
+        });
+        let name = form.name.trim();
+        if ui
+            .add_enabled(!name.is_empty(), Button::new("Save selected lane"))
+            .on_hover_text("Replaces the preset with the same name in the folder")
+            .clicked()
+        {
+            preset::insert(
+                &mut self.presets,
+                Preset {
+                    name: name.to_owned(),
+                    folder: form.folder.trim().to_owned(),
+                    tags: preset::parse_tags(&form.tags),
+                    curve: self.lanes[self.selected].curve.clone(),
+                },
+            );
+        }
+
+        ui.separator();
+        let tags = preset::tags(&self.presets);
+        ComboBox::from_label("Tag")
+            .selected_text(form.tag_filter.as_deref().unwrap_or("All"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut form.tag_filter, None, "All");
+                for tag in tags {
+                    ui.selectable_value(&mut form.tag_filter, Some(tag.to_owned()), tag);
+                }
+            });
+
+        let mut load = None;
+        let mut remove = None;
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for folder in preset::folders(&self.presets) {
+                    let title = if folder.is_empty() {
+                        "No folder"
+                    } else {
+                        folder
+                    };
+                    egui::CollapsingHeader::new(title)
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            let shown = self.presets.iter().enumerate().filter(|(_, preset)| {
+                                preset.folder == folder
+                                    && form
+                                        .tag_filter
+                                        .as_ref()
+                                        .map_or(true, |tag| preset.has_tag(tag))
+                            });
+                            for (i, preset) in shown {
+                                ui.horizontal(|ui| {
+                                    let thumbnail = preset
+                                        .curve
+                                        .draw_thumbnail(ui, egui::vec2(64.0, 32.0))
+                                        .on_hover_text("Click to load into the selected lane");
+                                    if thumbnail.clicked() {
+                                        load = Some(i);
+                                    }
+                                    thumbnail.context_menu(|ui| {
+                                        if ui.button("Delete").clicked() {
+                                            remove = Some(i);
+                                            ui.close_menu();
+                                        }
+                                    });
+                                    ui.vertical(|ui| {
+                                        ui.label(&preset.name);
+                                        if !preset.tags.is_empty() {
+                                            ui.weak(preset.tags.join(", "));
+                                        }
+                                    });
+                                });
+                            }
+                        });
+                }
+            });
+        if let Some(i) = load {
+            *self.curve_mut() = self.presets[i].curve.clone();
+            self.selection.clear();
+        }
+        if let Some(i) = remove {
+            self.presets.remove(i);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Pack file");
+            ui.text_edit_singleline(&mut self.preset_path);
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .button("Import")
+                .on_hover_text("Adds the presets of the pack, replacing presets with the same name")
+                .clicked()
+            {
+                self.preset_form.message = match preset::load_pack(&self.preset_path) {
+                    Ok(presets) => {
+                        let count = presets.len();
+                        for preset in presets {
+                            preset::insert(&mut self.presets, preset);
+                        }
+                        format!("Imported {count} presets")
+                    }
+                    Err(err) => err.to_string(),
+                };
+            }
+            if ui
+                .button("Export")
+                .on_hover_text("Saves all presets")
+                .clicked()
+            {
+                self.preset_form.message = match preset::save_pack(&self.presets, &self.preset_path)
+                {
+                    Ok(()) => format!("Exported {} presets", self.presets.len()),
+                    Err(err) => err.to_string(),
+                };
+            }
+        });
+        if !self.preset_form.message.is_empty() {
+            ui.weak(&self.preset_form.message);
+        }
+    }
+
+    fn generator_ui(&mut self, ui: &mut egui::Ui) {
+        let generator = &mut self.generator;
+        egui::Grid::new("generator").num_columns(2).show(ui, |ui| {
+            ui.label("Waveform");
+            ComboBox::from_id_source("waveform")
+                .selected_text(generator.waveform.to_string())
+                .show_ui(ui, |ui| {
+                    for waveform in Waveform::ALL {
+                        ui.selectable_value(
+                            &mut generator.waveform,
+                            waveform,
+                            waveform.to_string(),
+                        );
+                    }
+                });
+            ui.end_row();
+            ui.label("Cycles per loop");
+            ui.add(DragValue::new(&mut generator.cycles).clamp_range(1..=64));
+            ui.end_row();
+            ui.label("Phase");
+            ui.add(Slider::new(&mut generator.phase, 0.0..=1.0))
+                .on_hover_text("Fraction of a cycle the loop starts at");
+            ui.end_row();
+            ui.label("Amplitude");
+            ui.add(Slider::new(&mut generator.amplitude, 0.0..=1.0))
+                .on_hover_text("Fraction of the range between the lowest and highest value");
+            ui.end_row();
+            ui.label("Offset");
+            ui.add(Slider::new(&mut generator.offset, 0.0..=1.0))
+                .on_hover_text("Fraction of the range in the middle of the waveform");
+            ui.end_row();
+            ui.label("Duty cycle");
+            ui.add_enabled(
+                generator.waveform == Waveform::Square,
+                Slider::new(&mut generator.duty, 0.0..=1.0),
+            );
+            ui.end_row();
+            ui.label("Seed");
+            ui.add_enabled_ui(generator.waveform == Waveform::Random, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut generator.seed));
+                    if ui.button("New").clicked() {
+                        generator.seed =
+                            Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+                    }
+                });
+            });
+            ui.end_row();
+        });
+
+        ui.separator();
+        if ui
+            .button("Replace curve")
+            .on_hover_text("Keeps the loop length and range of the selected lane")
+            .clicked()
+        {
+            let curve = self.generator.curve(&self.lanes[self.selected].curve);
+            *self.curve_mut() = curve;
+            self.selection.clear();
+        }
+    }
+
+    fn shortcuts_ui(ui: &mut egui::Ui) {
+        egui::Grid::new("shortcuts")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for (shortcut, action) in [
+                    (UNDO_SHORTCUT, "Undo"),
+                    (REDO_SHORTCUT, "Redo"),
+                    (CUT_SHORTCUT, "Cut the selected beats"),
+                    (COPY_SHORTCUT, "Copy the selected beats"),
+                    (PASTE_SHORTCUT, "Paste at the mouse pointer or the playhead"),
+                    (SHORTCUTS_SHORTCUT, "Show this overview"),
+                    (TAP_SHORTCUT, "Tap the tempo"),
+                    (PLAY_SHORTCUT, "Play or pause"),
+                    (DOWNBEAT_SHORTCUT, "Restart the loop region now"),
+                    (SLOWER_SHORTCUT, "Hold to play slower"),
+                    (FASTER_SHORTCUT, "Hold to play faster"),
+                ] {
+                    ui.label(ui.ctx().format_shortcut(&shortcut));
+                    ui.label(action);
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+        ui.weak("In edit mode");
+        egui::Grid::new("curve_shortcuts")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for (keys, action) in curve::keyboard::SHORTCUTS {
+                    ui.label(keys);
+                    ui.label(action);
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn history_ui(&mut self, ui: &mut egui::Ui) {
+        let undo = Button::new("Undo").shortcut_text(ui.ctx().format_shortcut(&UNDO_SHORTCUT));
+        if ui.add_enabled(self.history.can_undo(), undo).clicked() {
+            self.history.undo(&mut self.lanes);
+            self.selection.clear();
+            ui.close_menu();
+        }
+        let redo = Button::new("Redo").shortcut_text(ui.ctx().format_shortcut(&REDO_SHORTCUT));
+        if ui.add_enabled(self.history.can_redo(), redo).clicked() {
+            self.history.redo(&mut self.lanes);
+            self.selection.clear();
+            ui.close_menu();
+        }
+
+        ui.separator();
+        let has_range = self.selected_range().is_some();
+        let cut = Button::new("Cut").shortcut_text(ui.ctx().format_shortcut(&CUT_SHORTCUT));
+        if ui.add_enabled(has_range, cut).clicked() {
+            self.copy(ui.ctx(), true);
+            ui.close_menu();
+        }
+        let copy = Button::new("Copy").shortcut_text(ui.ctx().format_shortcut(&COPY_SHORTCUT));
+        if ui.add_enabled(has_range, copy).clicked() {
+            self.copy(ui.ctx(), false);
+            ui.close_menu();
+        }
+        let paste = Button::new("Paste at playhead")
+            .shortcut_text(ui.ctx().format_shortcut(&PASTE_SHORTCUT));
+        if ui
+            .add_enabled(!self.clipboard.is_empty(), paste)
+            .on_hover_text("The shortcut pastes at the mouse pointer")
+            .clicked()
+        {
+            self.paste(&self.clipboard.clone(), None);
+            ui.close_menu();
+        }
+    }
+
+    fn snap_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.snap.enabled, "Snap to grid")
+            .on_hover_text("Hold Alt while dragging to invert");
+
+        ui.separator();
+        for (subdivision, name) in SUBDIVISIONS {
+            ui.radio_value(&mut self.snap.subdivision, subdivision, name);
+        }
+
+        ui.separator();
+        let mut snap_values = self.snap.value_step.is_some();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut snap_values, "Value steps");
+            let mut step = self.snap.value_step.unwrap_or(1.0);
+            let range = self.lanes[self.selected].curve.range();
+            ui.add_enabled(
+                snap_values,
+                DragValue::new(&mut step)
+                    .clamp_range(0.001..=f32::MAX)
+                    .speed((range.max - range.min).abs() * 0.001)
+                    .suffix(format!(" {}", range.unit)),
+            );
+            self.snap.value_step = Some(step).filter(|_| snap_values);
+        });
+    }
+
+    fn range_ui(&mut self, ui: &mut egui::Ui) {
+        let curve = self.curve_mut();
+        let mut range = curve.range().clone();
+        let speed = (range.max - range.min).abs() * 0.005;
+
+        egui::Grid::new("range").num_columns(2).show(ui, |ui| {
+            ui.label("Min");
+            ui.add(DragValue::new(&mut range.min).speed(speed));
+            ui.end_row();
+            ui.label("Max");
+            ui.add(DragValue::new(&mut range.max).speed(speed));
+            ui.end_row();
+            ui.label("Unit");
+            ui.text_edit_singleline(&mut range.unit);
+            ui.end_row();
+        });
+        ui.add_enabled(
+            range.min > 0.0 && range.max > 0.0,
+            Checkbox::new(&mut range.logarithmic, "Logarithmic"),
+        );
+
+        ui.separator();
+        for (name, preset) in [
+            ("Default 0..100", ValueRange::default()),
+            ("DMX 0..255", ValueRange::new(0.0, 255.0, "")),
+            ("Pan -1..1", ValueRange::new(-1.0, 1.0, "")),
+            (
+                "Cutoff 20..20000 Hz",
+                ValueRange::new(20.0, 20000.0, "Hz").logarithmic(),
+            ),
+        ] {
+            if ui.button(name).clicked() {
+                range = preset;
+                ui.close_menu();
+            }
+        }
+
+        if range != *curve.range() {
+            curve.set_range(range);
+        }
     }
 }
 
 impl eframe::App for TemplateApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.run {
-            self.x = (((Utc::now().naive_utc() - self.start).num_milliseconds() as f64 / 500.0)
-                % 4.0) as f32;
+        // Text fields have their own undo
+        if !ctx.wants_keyboard_input() {
+            // Redo first, its shortcut includes the one of undo
+            if ctx.input_mut(|input| input.consume_shortcut(&REDO_SHORTCUT)) {
+                self.history.redo(&mut self.lanes);
+                self.selection.clear();
+            } else if ctx.input_mut(|input| input.consume_shortcut(&UNDO_SHORTCUT)) {
+                self.history.undo(&mut self.lanes);
+                self.selection.clear();
+            }
+            if ctx.input_mut(|input| input.consume_shortcut(&SHORTCUTS_SHORTCUT)) {
+                self.show_shortcuts = !self.show_shortcuts;
+            }
+        }
+
+        // Undo may have removed the selected lane
+        self.selected = self.selected.min(self.lanes.len() - 1);
+
+        // The playhead runs through the longest lane and all lanes start over together at its end.
+        // Shorter lanes loop on their own until then, so lanes of different lengths only keep
+        // their phase if the longest one is a multiple of them.
+        let length = self
+            .lanes
+            .iter()
+            .map(|lane| lane.curve.length())
+            .fold(0.0, f32::max);
+        let mut nudge = 0;
+        if !ctx.wants_keyboard_input() {
+            ctx.input_mut(|input| {
+                if input.consume_shortcut(&PLAY_SHORTCUT) {
+                    if self.transport.is_playing() {
+                        self.transport.pause();
+                    } else {
+                        self.transport.play();
+                    }
+                }
+                if input.consume_shortcut(&TAP_SHORTCUT) {
+                    self.transport.tap();
+                }
+                if input.consume_shortcut(&DOWNBEAT_SHORTCUT) {
+                    self.transport.restart();
+                }
+                nudge = input.key_down(FASTER_SHORTCUT.logical_key) as i32
+                    - input.key_down(SLOWER_SHORTCUT.logical_key) as i32;
+            });
+        }
+
+        // The transport plays on its own, the UI only shows where it is
+        self.loop_region = self.loop_region.filter(|(_, end)| *end <= length);
+        let (loop_start, loop_end) = self.loop_region.unwrap_or((0.0, length));
+        self.transport
+            .set_loop_region(loop_start as f64, loop_end as f64);
+        self.x = (self.transport.position() as f32).min(length);
+        // Not in the sync menu, it is usually closed when the device goes away
+        if let Some(input) = &self.midi_input {
+            if !input.is_connected() {
+                self.midi_error = Some(
+                    input
+                        .error()
+                        .unwrap_or_else(|| "The MIDI clock input stopped".to_owned()),
+                );
+                self.midi_input = None;
+            }
+        }
+        if self.transport.is_playing() {
             ctx.request_repaint();
+        } else if self.midi_input.is_some() {
+            // Start and continue arrive from another thread
+            ctx.request_repaint_after(std::time::Duration::from_millis(50));
         }
 
         egui::TopBottomPanel::top("top").show(ctx, |ui| {
             ui.horizontal(|ui| {
+                ui.menu_button("Edit", |ui| self.history_ui(ui));
                 ui.checkbox(&mut self.show_progress, "Values");
-                ui.add_enabled(
-                    self.show_progress,
-                    Checkbox::new(&mut self.run, "Run with 120bpm"),
-                );
-                ui.add_enabled(
-                    self.show_progress,
-                    Slider::new(&mut self.x, 0.0f32..=4.0f32),
-                );
+                nudge += self.transport_ui(ui, length);
+                ui.menu_button("Sync", |ui| self.sync_ui(ui));
+                ui.menu_button("Output", |ui| self.output_ui(ui));
+                self.loop_ui(ui);
+                ui.menu_button("Range", |ui| self.range_ui(ui));
                 ui.checkbox(&mut self.edit_mode, "Edit mode");
+                ui.menu_button("Snap", |ui| self.snap_ui(ui));
+                ui.menu_button("Transform", |ui| self.transform_ui(ui));
+                ui.menu_button("View", |ui| {
+                    if ui.button("Fit to curve").clicked() {
+                        self.view = View::fit(&self.lanes[self.selected].curve);
+                        ui.close_menu();
+                    }
+                    if ui.button("Whole loop").clicked() {
+                        self.view = View::default();
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Help", |ui| {
+                    let shortcuts = Button::new("Keyboard shortcuts")
+                        .shortcut_text(ui.ctx().format_shortcut(&SHORTCUTS_SHORTCUT));
+                    if ui.add(shortcuts).clicked() {
+                        self.show_shortcuts = true;
+                        ui.close_menu();
+                    }
+                });
                 ui.menu_button("Examples", |ui| {
+                    if ui.button("Presets…").clicked() {
+                        self.show_presets = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Generate…").clicked() {
+                        self.show_generator = true;
+                        ui.close_menu();
+                    }
+                    ui.separator();
                     if ui.button("Forward").clicked() {
-                        self.curve = Curve::forward();
+                        *self.curve_mut() = Curve::forward();
                         ui.close_menu();
                     }
                     if ui.button("Backward").clicked() {
-                        self.curve = Curve::backward();
+                        *self.curve_mut() = Curve::backward();
                         ui.close_menu();
                     }
                     if ui.button("Alternating").clicked() {
-                        self.curve = Curve::alternating();
+                        *self.curve_mut() = Curve::alternating();
                         ui.close_menu();
                     }
                     if ui.button("Fixed").clicked() {
-                        self.curve = Curve::fixed();
+                        *self.curve_mut() = Curve::fixed();
                         ui.close_menu();
                     }
                 })
             });
         });
+        self.transport.nudge(nudge);
+
+        egui::SidePanel::left("lanes").show(ctx, |ui| self.lanes_ui(ui));
+        if self.edit_mode {
+            egui::SidePanel::right("inspector").show(ctx, |ui| self.inspector_ui(ui));
+        }
+
+        let mut show_presets = self.show_presets;
+        egui::Window::new("Presets")
+            .open(&mut show_presets)
+            .show(ctx, |ui| self.presets_ui(ui));
+        self.show_presets &= show_presets;
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            self.curve.draw(
+        let mut show_generator = self.show_generator;
+        egui::Window::new("Generate curve")
+            .open(&mut show_generator)
+            .resizable(false)
+            .show(ctx, |ui| self.generator_ui(ui));
+        self.show_generator &= show_generator;
+
+        egui::Window::new("Keyboard shortcuts")
+            .open(&mut self.show_shortcuts)
+            .resizable(false)
+            .show(ctx, Self::shortcuts_ui);
+
+        // Tab selects the next point instead of focusing the next widget
+        let cycling = self.edit_mode
+            && !ctx.wants_keyboard_input()
+            && ctx.input(|input| input.key_pressed(Key::Tab));
+        if self.edit_mode && !ctx.wants_keyboard_input() {
+            self.lanes[self.selected]
+                .curve
+                .keyboard(ctx, &self.snap, &mut self.selection, self.x);
+        }
+
+        let hover_beat = egui::CentralPanel::default().show(ctx, |ui| {
+            self.lanes[self.selected].curve.draw(
                 ui,
+                &mut self.view,
+                &self.snap,
+                &mut self.selection,
                 Some(self.x).filter(|_| self.show_progress),
                 self.edit_mode,
-            );
+            )
         });
+
+        if self.edit_mode && !ctx.wants_keyboard_input() {
+            let events = ctx.input(|input| input.events.clone());
+            for event in events {
+                match event {
+                    egui::Event::Copy => self.copy(ctx, false),
+                    egui::Event::Cut => self.copy(ctx, true),
+                    egui::Event::Paste(text) => self.paste(&text, hover_beat.inner),
+                    _ => {}
+                }
+            }
+        }
+
+        if cycling {
+            ctx.memory_mut(|memory| {
+                if let Some(id) = memory.focus() {
+                    memory.surrender_focus(id);
+                }
+            });
+        }
+
+        // A drag, held arrow keys or typing into a field like the lane name are recorded as one
+        // step once they are released or the field loses focus
+        let editing = ctx.wants_keyboard_input()
+            || ctx.input(|input| {
+                input.pointer.any_down()
+                    || [
+                        Key::ArrowLeft,
+                        Key::ArrowRight,
+                        Key::ArrowUp,
+                        Key::ArrowDown,
+                    ]
+                    .into_iter()
+                    .any(|key| input.key_down(key))
+            });
+        self.history.record(&self.lanes, editing);
+        self.update_outputs();
     }
 }
@@ -1,14 +1,51 @@
+mod clip;
+mod edit;
+mod evaluate;
+mod file;
+mod generator;
+mod inspector;
+mod interpolation;
+pub mod keyboard;
 mod point;
+pub mod snap;
+pub mod time_signature;
+mod transform;
+mod value_range;
+mod view;
 
-use self::point::CurvePoint;
-use egui::{epaint::QuadraticBezierShape, Color32, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2};
-use epaint::PathShape;
+pub use self::{
+    clip::Clip,
+    edit::{Edit, EditError},
+    evaluate::{EvaluateError, OutOfRange},
+    file::FileError,
+    generator::{Generator, Waveform},
+    interpolation::Interpolation,
+    snap::Snap,
+    time_signature::TimeSignature,
+    value_range::ValueRange,
+    view::View,
+};
 
-const PRECISION: f32 = 0.005; // 100 updates per second at 240 bpm
+use self::{file::CurveFile, point::CurvePoint};
+use egui::{
+    epaint::{CubicBezierShape, QuadraticBezierShape},
+    Align2, Color32, FontId, PointerButton, Pos2, Rect, Response, Sense, Shape, Stroke, Ui, Vec2,
+};
+use emath::RectTransform;
+use epaint::PathShape;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "CurveFile", try_from = "CurveFile")]
 pub struct Curve {
     linked: bool,
+    time_signature: TimeSignature,
+    bars: u32,
+    range: ValueRange,
     points: Vec<CurvePoint>,
+    /// One for every segment between two anchors
+    interpolations: Vec<Interpolation>,
 }
 
 impl Default for Curve {
@@ -19,9 +56,9 @@ impl Default for Curve {
 
 impl Curve {
     pub fn forward() -> Self {
-        Self {
-            linked: false,
-            points: Self::linear_points(vec![
+        Self::from_points(
+            false,
+            vec![
                 (0.0, 100.0),
                 (1.0, 0.0),
                 (1.0, 100.0),
@@ -30,46 +67,155 @@ impl Curve {
                 (3.0, 0.0),
                 (3.0, 100.0),
                 (4.0, 0.0),
-            ]),
-        }
+            ],
+        )
     }
 
     pub fn backward() -> Self {
-        Self {
-            linked: false,
-            points: Self::linear_points(vec![
-                (0.0, 0.0),
-                (1.0, 100.0),
-                (1.0, 0.0),
-                (2.0, 100.0),
-                (2.0, 0.0),
-                (3.0, 100.0),
-                (3.0, 0.0),
-                (4.0, 100.0),
-            ]),
-        }
+        let mut curve = Self::forward();
+        curve.reverse();
+        curve
     }
 
     pub fn alternating() -> Self {
-        Self {
-            linked: true,
-            points: Self::linear_points(vec![
+        Self::from_points(
+            true,
+            vec![
                 (0.0, 100.0),
                 (1.0, 0.0),
                 (2.0, 100.0),
                 (3.0, 0.0),
                 (4.0, 100.0),
-            ]),
-        }
+            ],
+        )
     }
 
     pub fn fixed() -> Self {
+        Self::from_points(true, vec![(0.0, 0.0), (4.0, 0.0)])
+    }
+
+    /// First and last point always have the same value
+    pub fn linked(&self) -> bool {
+        self.linked
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    pub fn bars(&self) -> u32 {
+        self.bars
+    }
+
+    /// Length of the loop in beats
+    pub fn length(&self) -> f32 {
+        (self.bars * self.time_signature.beats) as f32
+    }
+
+    /// Changes the loop length, the points are stretched to keep the shape of the curve
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature, bars: u32) {
+        if !time_signature.is_valid() || bars == 0 {
+            return;
+        }
+
+        let old_length = self.length();
+        self.time_signature = time_signature;
+        self.bars = bars;
+        let length = self.length();
+        for point in &mut self.points {
+            point.set_x(point.pos().x * length / old_length);
+        }
+        // Avoid rounding errors at the loop end
+        if let Some(last) = self.points.last_mut() {
+            last.set_x(length);
+        }
+    }
+
+    pub fn range(&self) -> &ValueRange {
+        &self.range
+    }
+
+    /// Changes the output values, the shape of the curve stays the same
+    pub fn set_range(&mut self, range: ValueRange) {
+        if range.is_valid() {
+            self.range = range;
+        }
+    }
+
+    /// Points are stored with y from 0.0 at the top (max) to 100.0 at the bottom (min)
+    fn value_to_y(&self, value: f32) -> f32 {
+        100.0 * (1.0 - self.range.normalized(value))
+    }
+
+    fn y_to_value(&self, y: f32) -> f32 {
+        self.range.value(1.0 - y / 100.0)
+    }
+
+    /// Anchor indices at the start and end of every segment
+    fn segments(&self) -> Vec<(usize, usize)> {
+        let anchors: Vec<usize> = self
+            .points
+            .iter()
+            .enumerate()
+            .filter(|(_, point)| point.is_anchor())
+            .map(|(i, _)| i)
+            .collect();
+        anchors
+            .windows(2)
+            .map(|anchors| (anchors[0], anchors[1]))
+            .collect()
+    }
+
+    /// Index of the segment containing `x`, at vertical jumps the segment after the jump
+    fn segment_at(&self, x: f32) -> Option<usize> {
+        self.segments()
+            .into_iter()
+            .position(|(_, end)| self.points[end].pos().x > x)
+    }
+
+    /// Index of the segment the bezier point `i` belongs to
+    fn segment_of(&self, i: usize) -> Option<usize> {
+        self.segments()
+            .into_iter()
+            .position(|(start, end)| start < i && i < end)
+    }
+
+    /// Bezier points only shape bezier segments, the others are not shown or editable
+    fn hidden_points(&self) -> Vec<bool> {
+        let segments = self.segments();
+        (0..self.points.len())
+            .map(|i| {
+                segments
+                    .iter()
+                    .zip(&self.interpolations)
+                    .any(|((start, end), interpolation)| {
+                        *start < i && i < *end && *interpolation != Interpolation::Bezier
+                    })
+            })
+            .collect()
+    }
+
+    fn from_points(linked: bool, points: Vec<(f32, f32)>) -> Self {
+        let points = Self::linear_points(points);
         Self {
-            linked: true,
-            points: Self::linear_points(vec![(0.0, 0.0), (4.0, 0.0)]),
+            linked,
+            time_signature: TimeSignature::default(),
+            bars: 1,
+            range: ValueRange::default(),
+            interpolations: vec![Interpolation::default(); points.len() / 2],
+            points,
         }
     }
 
+    /// Position of point `index` in curve coordinates
+    pub fn position(&self, index: usize) -> Option<Pos2> {
+        self.points.get(index).map(CurvePoint::pos)
+    }
+
+    pub fn interpolation(&self, segment: usize) -> Option<Interpolation> {
+        self.interpolations.get(segment).copied()
+    }
+
     fn linear_points(points: Vec<(f32, f32)>) -> Vec<CurvePoint> {
         let mut linear_points = Vec::with_capacity(2 * points.len() - 1);
         let mut prev = None;
@@ -95,109 +241,327 @@ impl Curve {
         linear_points
     }
 
-    pub fn draw(&mut self, ui: &mut Ui, beat_position: Option<f32>, edit_mode: bool) {
-        let to_screen = emath::RectTransform::from_to(
-            Rect::from_min_size(Pos2::ZERO, Vec2::new(4.0, 100.0)),
-            Rect::from_min_size(ui.next_widget_position(), ui.available_size()),
+    /// Transformations of the selected points, `group` has more than one point
+    fn group_menu_ui(&self, ui: &mut Ui, group: &[usize]) -> Option<Edit> {
+        let bounds = Rect::from_points(
+            &group
+                .iter()
+                .map(|i| self.points[*i].pos())
+                .collect::<Vec<_>>(),
+        );
+        // Time stretches away from the first point, values around the middle
+        let origin = Pos2::new(bounds.min.x, bounds.center().y);
+        let scale = |factor: Vec2| Edit::ScalePoints {
+            indices: group.to_vec(),
+            origin,
+            factor,
+        };
+
+        if ui.button("Flip values").clicked() {
+            return Some(scale(Vec2::new(1.0, -1.0)));
+        }
+        if ui.button("Reverse in time").clicked() {
+            return Some(Edit::ReversePoints {
+                indices: group.to_vec(),
+            });
+        }
+        for (name, factor) in [
+            ("Stretch time ×2", Vec2::new(2.0, 1.0)),
+            ("Compress time ×½", Vec2::new(0.5, 1.0)),
+            ("Scale values ×2", Vec2::new(1.0, 2.0)),
+            ("Scale values ×½", Vec2::new(1.0, 0.5)),
+        ] {
+            if ui.button(name).clicked() {
+                return Some(scale(factor));
+            }
+        }
+        None
+    }
+
+    /// Shapes of all segments, shared by `draw` and `draw_thumbnail`
+    fn segment_shapes(&self, to_screen: RectTransform, stroke: Stroke) -> Vec<Shape> {
+        let points_in_screen: Vec<Pos2> = self
+            .points
+            .iter()
+            .map(|p| p.screen_pos(to_screen))
+            .collect();
+        let mut shapes = Vec::new();
+        for ((start, end), interpolation) in self.segments().into_iter().zip(&self.interpolations) {
+            match (interpolation, &points_in_screen[start..=end]) {
+                (Interpolation::Bezier, [from, control, to]) => {
+                    shapes.push(
+                        QuadraticBezierShape::from_points_stroke(
+                            [*from, *control, *to],
+                            false,
+                            Color32::TRANSPARENT,
+                            stroke,
+                        )
+                        .into(),
+                    );
+                }
+                (Interpolation::Bezier, [from, first, second, to]) => {
+                    shapes.push(
+                        CubicBezierShape::from_points_stroke(
+                            [*from, *first, *second, *to],
+                            false,
+                            Color32::TRANSPARENT,
+                            stroke,
+                        )
+                        .into(),
+                    );
+                }
+                (Interpolation::Bezier, _) => {}
+                (interpolation, _) => {
+                    let path = interpolation
+                        .path(self.points[start].pos(), self.points[end].pos())
+                        .into_iter()
+                        .map(|pos| to_screen.transform_pos(pos))
+                        .collect();
+                    shapes.push(PathShape::line(path, stroke).into());
+                }
+            }
+        }
+        shapes
+    }
+
+    /// Small preview of the whole loop, e.g. for lists of curves
+    pub fn draw_thumbnail(&self, ui: &mut Ui, size: Vec2) -> Response {
+        let (response, painter) = ui.allocate_painter(size, Sense::click());
+        let visuals = ui.style().interact(&response);
+        painter.rect(
+            response.rect,
+            visuals.rounding,
+            ui.visuals().extreme_bg_color,
+            visuals.bg_stroke,
+        );
+        let to_screen = RectTransform::from_to(
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(self.length(), 100.0)),
+            response.rect.shrink(3.0),
+        );
+        painter.extend(
+            self.segment_shapes(to_screen, Stroke::new(1.0, Color32::from_rgb(25, 200, 100))),
         );
+        response
+    }
+
+    /// Returns the beat under the pointer
+    pub fn draw(
+        &mut self,
+        ui: &mut Ui,
+        view: &mut View,
+        snap: &Snap,
+        selection: &mut BTreeSet<usize>,
+        beat_position: Option<f32>,
+        edit_mode: bool,
+    ) -> Option<f32> {
+        let length = self.length();
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
 
-        let mut outer_change = None;
-        let mut bezier_to_line = None;
-        let mut remove_point = None;
+        // Scrolling zooms the beats, scrolling sideways (shift on most mice) the values and
+        // pinching or ctrl scrolling both
+        let to_screen = RectTransform::from_to(view.visible(length), response.rect);
+        if let Some(hover_pos) = response.hover_pos() {
+            let (scroll, zoom) = ui.input(|input| (input.smooth_scroll_delta, input.zoom_delta()));
+            let factor = Vec2::new((scroll.y / 200.0).exp(), (scroll.x / 200.0).exp()) * zoom;
+            if factor != Vec2::splat(1.0) {
+                view.zoom(length, factor, to_screen.inverse().transform_pos(hover_pos));
+                ui.ctx().request_repaint();
+            }
+        }
+        // The primary button edits points in edit mode
+        if response.dragged_by(PointerButton::Middle)
+            || (!edit_mode && response.dragged_by(PointerButton::Primary))
+        {
+            view.pan(length, -response.drag_delta() / to_screen.scale());
+        }
+        let visible = view.visible(length);
+        let to_screen = RectTransform::from_to(visible, response.rect);
 
-        let (response, painter) = ui.allocate_painter(to_screen.to().size(), Sense::hover());
+        // Only as fine as there is room for
+        const MIN_GRID_SPACING: f32 = 6.0;
+        let subdivision = snap.subdivision.max(1);
+        if to_screen.scale().x / subdivision as f32 >= MIN_GRID_SPACING {
+            let first = (visible.min.x * subdivision as f32).floor() as u32;
+            let last = (visible.max.x * subdivision as f32).ceil() as u32;
+            for step in (first..=last).filter(|step| step % subdivision != 0) {
+                let x = step as f32 / subdivision as f32;
+                painter.add(PathShape::line(
+                    vec![
+                        to_screen.transform_pos(Pos2::new(x, 0.0)),
+                        to_screen.transform_pos(Pos2::new(x, 100.0)),
+                    ],
+                    Stroke::new(0.5, Color32::GRAY.linear_multiply(0.3)),
+                ));
+            }
+        }
+        let max_value_steps = (response.rect.height() / MIN_GRID_SPACING) as usize;
+        for value in snap
+            .value_step
+            .and_then(|step| self.value_steps(step, max_value_steps))
+            .unwrap_or_default()
+        {
+            let y = self.value_to_y(value);
+            painter.add(PathShape::line(
+                vec![
+                    to_screen.transform_pos(Pos2::new(0.0, y)),
+                    to_screen.transform_pos(Pos2::new(length, y)),
+                ],
+                Stroke::new(0.5, Color32::GRAY.linear_multiply(0.3)),
+            ));
+        }
 
-        for i in 0..=4 {
+        let beats = self.bars * self.time_signature.beats;
+        for beat in 0..=beats {
             let stroke = Stroke::new(
-                match i {
-                    0 | 4 => 1.0,
-                    _ => 0.5,
+                if beat % self.time_signature.beats == 0 {
+                    1.0
+                } else {
+                    0.5
                 },
                 Color32::GRAY,
             );
             painter.add(PathShape::line(
                 vec![
-                    to_screen.transform_pos(Pos2::new(i as f32, 0.0)),
-                    to_screen.transform_pos(Pos2::new(i as f32, 100.0)),
+                    to_screen.transform_pos(Pos2::new(beat as f32, 0.0)),
+                    to_screen.transform_pos(Pos2::new(beat as f32, 100.0)),
                 ],
                 stroke,
             ));
+        }
+        for y in [0.0, 100.0] {
             painter.add(PathShape::line(
                 vec![
-                    to_screen.transform_pos(Pos2::new(0.0, (i * 25) as f32)),
-                    to_screen.transform_pos(Pos2::new(4.0, (i * 25) as f32)),
+                    to_screen.transform_pos(Pos2::new(0.0, y)),
+                    to_screen.transform_pos(Pos2::new(length, y)),
                 ],
-                stroke,
+                Stroke::new(1.0, Color32::GRAY),
+            ));
+        }
+        for value in self.range.grid_values() {
+            let y = self.value_to_y(value);
+            painter.add(PathShape::line(
+                vec![
+                    to_screen.transform_pos(Pos2::new(0.0, y)),
+                    to_screen.transform_pos(Pos2::new(length, y)),
+                ],
+                Stroke::new(0.5, Color32::GRAY),
             ));
+            // keep the labels of the outer lines inside the canvas
+            let (anchor, offset) = if y < 50.0 {
+                (Align2::LEFT_TOP, Vec2::new(2.0, 1.0))
+            } else {
+                (Align2::LEFT_BOTTOM, Vec2::new(2.0, -1.0))
+            };
+            painter.text(
+                to_screen.transform_pos(Pos2::new(visible.min.x, y)) + offset,
+                anchor,
+                self.range.format(value),
+                FontId::proportional(10.0),
+                Color32::GRAY,
+            );
         }
 
+        // Coordinates of the hovered point, otherwise of the pointer
+        let mut hovered_point = None;
         if edit_mode {
+            let mut edits = Vec::new();
+            let snapping = snap.is_active(ui.input(|input| input.modifiers));
+            let snapped = |pos| if snapping { self.snap(pos, snap) } else { pos };
+            let shift = ui.input(|input| input.modifiers.shift);
+
             let new_point_id = response.id.with(self.points.len());
-            let new_point_response = ui.interact(*to_screen.to(), new_point_id, Sense::click());
-            if new_point_response.double_clicked()
-                || new_point_response.clicked_by(egui::PointerButton::Secondary)
-            {
-                if let Some((pos, i, before, after)) =
-                    new_point_response.interact_pointer_pos().and_then(|pos| {
+            let pointer_pos = response
+                .interact_pointer_pos()
+                .map(|pos| to_screen.inverse().transform_pos(pos));
+            if response.secondary_clicked() {
+                // The menu is shown in the following frames, remember where it was opened
+                ui.memory_mut(|memory| memory.data.insert_temp(new_point_id, pointer_pos));
+            }
+
+            // Applied last, the indices of the other edits refer to the points before inserting
+            let mut insert_at = pointer_pos.filter(|_| response.double_clicked());
+            response.context_menu(|ui| {
+                let Some(pos) = ui
+                    .memory(|memory| memory.data.get_temp(new_point_id))
+                    .flatten()
+                else {
+                    ui.close_menu();
+                    return;
+                };
+                if ui.button("Insert point").clicked() {
+                    insert_at = Some(pos);
+                    ui.close_menu();
+                }
+                if let Some(segment) = self.segment_at(pos.x) {
+                    ui.separator();
+                    for interpolation in Interpolation::ALL {
+                        if ui
+                            .radio(
+                                self.interpolations[segment] == interpolation,
+                                interpolation.to_string(),
+                            )
+                            .clicked()
+                        {
+                            edits.push(Edit::SetInterpolation {
+                                segment,
+                                interpolation,
+                            });
+                            ui.close_menu();
+                        }
+                    }
+                }
+            });
+
+            let hidden = self.hidden_points();
+            selection.retain(|i| !hidden.get(*i).copied().unwrap_or(true));
+
+            // Rubber band selection on the empty canvas
+            let band_id = response.id.with("band");
+            if response.clicked() && !shift {
+                selection.clear();
+            }
+            if response.drag_started_by(PointerButton::Primary) {
+                let start = ui.input(|input| input.pointer.press_origin());
+                ui.memory_mut(|memory| memory.data.insert_temp(band_id, start));
+            }
+            let band_start: Option<Pos2> =
+                ui.memory(|memory| memory.data.get_temp(band_id)).flatten();
+            if let (Some(start), Some(pointer)) = (band_start, response.interact_pointer_pos()) {
+                let band = Rect::from_two_pos(start, pointer);
+                if response.drag_released() {
+                    if !shift {
+                        selection.clear();
+                    }
+                    selection.extend(
                         self.points
                             .iter()
-                            .position(|point| point.screen_pos(to_screen).x > pos.x)
-                            .and_then(|i| {
-                                self.points.get(i).and_then(|after| {
-                                    self.points
-                                        .get(i - 1)
-                                        .map(|before| (pos, i, *before, *after))
-                                })
+                            .enumerate()
+                            .filter(|(i, point)| {
+                                !hidden[*i] && band.contains(point.screen_pos(to_screen))
                             })
-                    })
-                {
-                    if before.is_inner() || before.is_outer() {
-                        self.points
-                            .insert(i, CurvePoint::Inner(to_screen.inverse().transform_pos(pos)));
-                        self.points.insert(
-                            i,
-                            CurvePoint::Bezier(to_screen.inverse().transform_pos(Pos2::new(
-                                (before.screen_pos(to_screen).x + pos.x) / 2.0,
-                                (before.screen_pos(to_screen).y + pos.y) / 2.0,
-                            ))),
-                        );
-                        bezier_to_line = Some(i + 2);
-                    } else {
-                        bezier_to_line = Some(i - 1);
-                        self.points.insert(
-                            i,
-                            CurvePoint::Bezier(to_screen.inverse().transform_pos(Pos2::new(
-                                (after.screen_pos(to_screen).x + pos.x) / 2.0,
-                                (after.screen_pos(to_screen).y + pos.y) / 2.0,
-                            ))),
-                        );
-                        self.points
-                            .insert(i, CurvePoint::Inner(to_screen.inverse().transform_pos(pos)));
-                    }
+                            .map(|(i, _)| i),
+                    );
+                    ui.memory_mut(|memory| memory.data.remove::<Option<Pos2>>(band_id));
+                } else if response.dragged_by(PointerButton::Primary) {
+                    painter.rect(
+                        band,
+                        0.0,
+                        ui.visuals().selection.bg_fill.linear_multiply(0.2),
+                        ui.visuals().selection.stroke,
+                    );
                 }
             }
+            let group: Vec<usize> = selection.iter().copied().collect();
 
-            let x_limits = { 0..self.points.len() }
-                .map(|i| {
-                    if i == 0 {
-                        return None;
-                    }
-                    self.points
-                        .get(i - 1)
-                        .map(|point| point.screen_pos(to_screen).x)
-                        .and_then(|before| {
-                            self.points
-                                .get(i + 1)
-                                .map(|point| (before, point.screen_pos(to_screen).x))
-                        })
-                })
-                .collect::<Vec<_>>();
-
-            let control_point_shapes: Vec<Shape> = self
+            let point_responses: Vec<Option<Response>> = self
                 .points
-                .iter_mut()
+                .iter()
                 .enumerate()
                 .map(|(i, point)| {
+                    if hidden[i] {
+                        return None;
+                    }
+
                     let point_id = response.id.with(i);
                     let point_response = ui.interact(
                         point.point_rect(to_screen),
@@ -205,99 +569,167 @@ impl Curve {
                         Sense::click_and_drag(),
                     );
 
-                    if point_response.double_clicked()
-                        || point_response.clicked_by(egui::PointerButton::Secondary)
-                    {
+                    point_response.context_menu(|ui| {
                         if point.is_outer() {
-                            self.linked = !self.linked;
-                            if self.linked {
-                                outer_change = Some((i, point.pos()));
+                            let mut linked = self.linked;
+                            if ui.checkbox(&mut linked, "Linked").clicked() {
+                                edits.push(Edit::SetLinked { linked });
+                                ui.close_menu();
                             }
                         } else if point.is_bezier() {
-                            bezier_to_line = Some(i);
+                            let segment = self.segment_of(i).unwrap_or_default();
+                            if ui.button("Straighten").clicked() {
+                                edits.push(Edit::Straighten { segment });
+                                ui.close_menu();
+                            }
+                            if ui.button("Toggle cubic").clicked() {
+                                edits.push(Edit::ToggleCubic { segment });
+                                ui.close_menu();
+                            }
+                        } else {
+                            if ui.button("Remove").clicked() {
+                                edits.push(Edit::RemoveAnchor { index: i });
+                                ui.close_menu();
+                            }
+                            let mut smooth = point.is_smooth();
+                            if ui.checkbox(&mut smooth, "Smooth").clicked() {
+                                edits.push(Edit::SetSmooth { index: i, smooth });
+                                ui.close_menu();
+                            }
+                        }
+
+                        if group.len() > 1 && group.contains(&i) {
+                            ui.separator();
+                            if let Some(edit) = self.group_menu_ui(ui, &group) {
+                                edits.push(edit);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+
+                    if point_response.hovered() {
+                        hovered_point = Some(point.pos());
+                    }
+
+                    if point_response.clicked() {
+                        if !shift {
+                            selection.clear();
+                            selection.insert(i);
+                        } else if !selection.remove(&i) {
+                            selection.insert(i);
+                        }
+                    }
+
+                    if point_response.double_clicked() {
+                        if point.is_outer() {
+                            edits.push(Edit::SetLinked {
+                                linked: !self.linked,
+                            });
+                        } else if let Some(segment) = self.segment_of(i) {
+                            edits.push(Edit::Straighten { segment });
                         } else if point.is_inner() {
-                            remove_point = Some(i);
+                            edits.push(Edit::RemoveAnchor { index: i });
                         }
-                    } else {
-                        let mut new_screen_pos =
-                            point.screen_pos(to_screen) + point_response.drag_delta();
-                        if let Some(x_limit) = x_limits.get(i).and_then(|x_limit| *x_limit) {
-                            new_screen_pos.x = new_screen_pos.x.clamp(x_limit.0, x_limit.1)
+                    } else if point_response.dragged() {
+                        // Relative to the pointer, so snapping does not swallow small movements
+                        let grab_id = point_id.with("grab");
+                        let pointer = point_response.interact_pointer_pos().unwrap_or_default();
+                        if point_response.drag_started() {
+                            let press_origin = ui
+                                .input(|input| input.pointer.press_origin())
+                                .unwrap_or(pointer);
+                            let grab = point.screen_pos(to_screen) - press_origin;
+                            ui.memory_mut(|memory| memory.data.insert_temp(grab_id, grab));
                         }
-                        point.set_screen_pos(to_screen, new_screen_pos);
-                        if point_response.dragged() && point.is_outer() && self.linked {
-                            outer_change = Some((i, point.pos()));
+                        let grab: Vec2 = ui
+                            .memory(|memory| memory.data.get_temp(grab_id))
+                            .unwrap_or_default();
+                        let pos = snapped(to_screen.inverse().transform_pos(pointer + grab));
+                        // The dragged point takes the selected ones along
+                        if group.len() > 1 && group.contains(&i) {
+                            edits.push(Edit::MovePoints {
+                                indices: group.clone(),
+                                delta: pos - point.pos(),
+                            });
+                        } else {
+                            if !selection.contains(&i) {
+                                selection.clear();
+                                selection.insert(i);
+                            }
+                            edits.push(Edit::MovePoint { index: i, pos });
                         }
                     }
 
-                    let stroke = if point.is_outer() && self.linked {
-                        let mut stroke = ui.style().interact(&point_response).fg_stroke;
-                        stroke.color = Color32::LIGHT_BLUE;
-                        stroke
-                    } else {
-                        ui.style().interact(&point_response).fg_stroke
-                    };
-                    point.shape(to_screen, stroke)
+                    Some(point_response)
                 })
                 .collect();
 
-            if let Some((i, pos)) = outer_change {
-                let i = self.points.len() - i - 1;
-                if let Some(point) = self.points.get_mut(i) {
-                    point.set_pos(pos);
-                }
-                ui.ctx().request_repaint();
+            if let Some(pos) = insert_at.map(snapped) {
+                edits.push(Edit::InsertAnchor {
+                    x: pos.x,
+                    y: Some(pos.y),
+                });
             }
-            if let Some(i) = remove_point {
-                self.points.remove(i);
-                self.points.remove(i);
-                bezier_to_line = Some(i - 1);
-                ui.ctx().request_repaint();
+            let changes_structure = edits.iter().any(|edit| {
+                matches!(
+                    edit,
+                    Edit::InsertAnchor { .. }
+                        | Edit::RemoveAnchor { .. }
+                        | Edit::ToggleCubic { .. }
+                        | Edit::ReversePoints { .. }
+                )
+            });
+            if changes_structure {
+                selection.clear();
             }
-            if let Some(i) = bezier_to_line {
-                if let (Some(before), Some(after), Some(point)) = (
-                    self.points.get(i - 1).map(|point| point.pos()),
-                    self.points.get(i + 1).map(|point| point.pos()),
-                    self.points.get_mut(i),
-                ) {
-                    point.set_pos(Pos2::new(
-                        (before.x + after.x) / 2.0,
-                        (before.y + after.y) / 2.0,
-                    ));
+            for edit in edits {
+                if let Err(err) = self.apply(&edit) {
+                    log::warn!("Could not apply {edit:?}: {err}");
                 }
                 ui.ctx().request_repaint();
             }
 
-            painter.extend(control_point_shapes);
+            // The responses do not match the points anymore, they are shown in the next frame
+            if !changes_structure {
+                for (i, (point, point_response)) in
+                    self.points.iter().zip(point_responses).enumerate()
+                {
+                    let Some(point_response) = point_response else {
+                        continue;
+                    };
+                    let mut stroke = ui.style().interact(&point_response).fg_stroke;
+                    if selection.contains(&i) {
+                        stroke.color = Color32::YELLOW;
+                    } else if point.is_outer() && self.linked {
+                        stroke.color = Color32::LIGHT_BLUE;
+                    }
+                    painter.add(point.shape(to_screen, stroke));
+                }
+            }
         }
 
-        let points_in_screen: Vec<Pos2> = self
-            .points
-            .iter()
-            .map(|p| p.screen_pos(to_screen))
-            .collect();
-        for i in 0..(points_in_screen.len() - 1) / 2 {
-            let shape = QuadraticBezierShape::from_points_stroke(
-                [
-                    points_in_screen[i * 2],
-                    points_in_screen[i * 2 + 1],
-                    points_in_screen[i * 2 + 2],
-                ],
-                false,
-                Color32::TRANSPARENT,
-                Stroke::new(1.0, Color32::from_rgb(25, 200, 100)),
-            );
-            painter.add(shape);
-        }
+        let curve_stroke = Stroke::new(1.0, Color32::from_rgb(25, 200, 100));
+        painter.extend(self.segment_shapes(to_screen, curve_stroke));
         if edit_mode {
-            painter.add(PathShape::line(
-                points_in_screen,
-                Stroke::new(1.0, Color32::RED.linear_multiply(0.25)),
-            ));
+            for ((start, end), interpolation) in
+                self.segments().into_iter().zip(&self.interpolations)
+            {
+                if *interpolation == Interpolation::Bezier {
+                    painter.add(PathShape::line(
+                        self.points[start..=end]
+                            .iter()
+                            .map(|point| point.screen_pos(to_screen))
+                            .collect(),
+                        Stroke::new(1.0, Color32::RED.linear_multiply(0.25)),
+                    ));
+                }
+            }
         }
 
         // draw current position
-        if let Some(beat_position) = beat_position {
+        if let Some(Ok(beat_position)) = beat_position
+            .map(|beat_position| self.position_in_loop(beat_position, OutOfRange::Wrap))
+        {
             painter.add(PathShape::line(
                 vec![
                     to_screen.transform_pos(Pos2::new(beat_position, 0.0)),
@@ -306,76 +738,33 @@ impl Curve {
                 Stroke::new(1.0, Color32::from_rgb(160, 0, 150)),
             ));
 
-            let y = 100.0 - self.value(beat_position);
+            let y = self.y(beat_position);
             painter.add(PathShape::line(
                 vec![
                     to_screen.transform_pos(Pos2::new(0.0, y)),
-                    to_screen.transform_pos(Pos2::new(4.0, y)),
+                    to_screen.transform_pos(Pos2::new(length, y)),
                 ],
                 Stroke::new(1.0, Color32::from_rgb(160, 0, 150)),
             ));
+            painter.text(
+                to_screen.transform_pos(Pos2::new(visible.max.x, y)) + Vec2::new(-2.0, -1.0),
+                Align2::RIGHT_BOTTOM,
+                self.range.format(self.y_to_value(y)),
+                FontId::proportional(14.0),
+                Color32::from_rgb(160, 0, 150),
+            );
         }
-    }
 
-    pub fn value(&self, beat_position: f32) -> f32 {
-        if !(0.0..=4.0).contains(&beat_position) {
-            panic!("Beat position out of range 0.0..=4.0: {beat_position}");
+        let hover_pos = response
+            .hover_pos()
+            .map(|pos| to_screen.inverse().transform_pos(pos));
+        let dragging = ui.input(|input| input.pointer.any_down());
+        if let Some(pos) = hovered_point.or(hover_pos).filter(|_| !dragging) {
+            egui::show_tooltip_at_pointer(ui.ctx(), response.id.with("coordinates"), |ui| {
+                ui.label(self.format_position(pos));
+            });
         }
 
-        let y = self
-            .points
-            .iter()
-            .position(|point| point.pos().x > beat_position)
-            .and_then(|i| {
-                self.points.get(i).and_then(|point| {
-                    if point.is_bezier() {
-                        self.points.get(i - 1).and_then(|start| {
-                            self.points
-                                .get(i + 1)
-                                .map(|end| (start.pos(), point.pos(), end.pos()))
-                        })
-                    } else {
-                        self.points.get(i - 1).and_then(|bezier| {
-                            self.points
-                                .get(i - 2)
-                                .map(|start| (start.pos(), bezier.pos(), point.pos()))
-                        })
-                    }
-                })
-            })
-            .map(|(start, bezier, end)| {
-                let bezier = QuadraticBezierShape::from_points_stroke(
-                    [start, bezier, end],
-                    false,
-                    Color32::TRANSPARENT,
-                    Stroke::default(),
-                );
-
-                // Binary search for t with (sample(t).x - beat_position).abs() < PRECISION
-                let mut n = 2;
-                let mut t = 0.5;
-                loop {
-                    let sample = bezier.sample(t);
-                    let e = sample.x - beat_position;
-                    if e.abs() < PRECISION {
-                        break sample.y;
-                    }
-                    let step = 1.0 / 2.0f32.powi(n);
-                    if e < 0.0 {
-                        t += step;
-                    } else {
-                        t -= step;
-                    }
-                    n += 1;
-                }
-            })
-            .unwrap_or_else(|| {
-                self.points
-                    .last()
-                    .expect("Could not get last point")
-                    .pos()
-                    .y
-            });
-        100.0 - y
+        hover_pos.map(|pos| pos.x)
     }
 }
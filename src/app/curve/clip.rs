@@ -0,0 +1,292 @@
+use super::{evaluate::segment_t, point::CurvePoint, Curve, EditError, FileError, Interpolation};
+use egui::Pos2;
+use serde::{Deserialize, Serialize};
+
+/// Identifies copied curve sections in the clipboard
+pub(super) const FORMAT: &str = "ui_experiments/clip";
+/// Bump whenever the layout changes, like the version of curve files
+pub(super) const VERSION: u32 = 1;
+
+/// A section of a curve, copied as text so it can be pasted into other curves and app instances
+///
+/// Values are stored relative to the range like the points of a curve, pasting into a curve with
+/// another range keeps the shape.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Clip {
+    format: String,
+    version: u32,
+    /// Beats covered, the points start at 0.0
+    width: f32,
+    /// Starts and ends with an anchor, there are no first and last points
+    points: Vec<CurvePoint>,
+    interpolations: Vec<Interpolation>,
+}
+
+impl Clip {
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("Could not serialize clip")
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, FileError> {
+        let clip: Clip = serde_json::from_str(text)?;
+        if clip.format != FORMAT {
+            return Err(FileError::Format(clip.format));
+        }
+        if clip.version > VERSION {
+            return Err(FileError::Version(clip.version));
+        }
+        clip.validate()?;
+        Ok(clip)
+    }
+
+    /// Same rules as for the points of a curve, only without first and last points
+    fn validate(&self) -> Result<(), FileError> {
+        let invalid = |index, reason| Err(FileError::Structure { index, reason });
+
+        if !self.width.is_finite() || self.width <= 0.0 {
+            return invalid(0, "a clip must cover some beats");
+        }
+        if self.points.len() < 3 {
+            return invalid(self.points.len(), "a clip needs at least two anchors");
+        }
+        let last = self.points.len() - 1;
+        let mut bezier_points = 0;
+        for (i, point) in self.points.iter().enumerate() {
+            let pos = point.pos();
+            if !pos.x.is_finite() || !pos.y.is_finite() {
+                return invalid(i, "position is not a finite number");
+            }
+            if !(0.0..=self.width).contains(&pos.x) || !(0.0..=100.0).contains(&pos.y) {
+                return invalid(i, "position is outside of the clip or 0.0..=100.0");
+            }
+            match point {
+                CurvePoint::First(_) | CurvePoint::Last(_) => {
+                    return invalid(i, "a clip has no first or last points")
+                }
+                CurvePoint::Bezier(_) if i == 0 || i == last => {
+                    return invalid(i, "a clip must start and end with an anchor")
+                }
+                CurvePoint::Bezier(_) => {
+                    bezier_points += 1;
+                    if bezier_points > 2 {
+                        return invalid(i, "more than two bezier points between anchors");
+                    }
+                }
+                _ if i > 0 && bezier_points == 0 => {
+                    return invalid(i, "anchors must be separated by a bezier point")
+                }
+                _ => bezier_points = 0,
+            }
+            if i > 0 && pos.x < self.points[i - 1].pos().x {
+                return invalid(i, "points are not ordered by x");
+            }
+        }
+        let segments = self.points.iter().filter(|point| point.is_anchor()).count() - 1;
+        if self.interpolations.len() != segments {
+            return invalid(last, "there must be one interpolation for every segment");
+        }
+        if self.points[0].pos().x != 0.0 || self.points[last].pos().x != self.width {
+            return invalid(0, "the points must span the whole clip");
+        }
+        Ok(())
+    }
+}
+
+impl Curve {
+    /// The part of the curve between the beats `from` and `to`
+    pub fn copy(&self, from: f32, to: f32) -> Result<Clip, EditError> {
+        self.check_range(from, to)?;
+        let mut curve = self.clone();
+        let (start, end) = curve.split_range(from, to);
+        let (first, last) = curve.segment_span(start, end);
+        let points = curve.points[start..=end]
+            .iter()
+            .map(|point| {
+                let pos = point.pos() - Pos2::new(from, 0.0).to_vec2();
+                match point {
+                    CurvePoint::First(_) | CurvePoint::Last(_) => CurvePoint::Inner(pos),
+                    CurvePoint::Inner(_) => CurvePoint::Inner(pos),
+                    CurvePoint::Smooth(_) => CurvePoint::Smooth(pos),
+                    CurvePoint::Bezier(_) => CurvePoint::Bezier(pos),
+                }
+            })
+            .collect();
+
+        Ok(Clip {
+            format: FORMAT.to_owned(),
+            version: VERSION,
+            width: to - from,
+            points,
+            interpolations: curve.interpolations[first..=last].to_vec(),
+        })
+    }
+
+    /// Copies the part between `from` and `to` and replaces it with a line
+    pub fn cut(&mut self, from: f32, to: f32) -> Result<Clip, EditError> {
+        let clip = self.copy(from, to)?;
+        let (start, end) = self.split_range(from, to);
+        let (first, last) = self.segment_span(start, end);
+        let (before, after) = (self.points[start].pos(), self.points[end].pos());
+        self.points.splice(
+            start + 1..end,
+            [CurvePoint::Bezier(before.lerp(after, 0.5))],
+        );
+        self.interpolations
+            .splice(first..=last, [Interpolation::Bezier]);
+        Ok(clip)
+    }
+
+    /// Replaces the part of the curve starting at the beat `at` with the clip
+    ///
+    /// Clips reaching past the end of the loop are moved back to fit, the points around the
+    /// pasted part keep their position.
+    pub fn paste(&mut self, clip: &Clip, at: f32) -> Result<(), EditError> {
+        let length = self.length();
+        if !at.is_finite() || clip.width > length {
+            return Err(EditError::Range(at, at + clip.width));
+        }
+        let from = at.clamp(0.0, length - clip.width);
+        let to = from + clip.width;
+
+        let (start, end) = self.split_range(from, to);
+        let (first, last) = self.segment_span(start, end);
+        let inner = &clip.points[1..clip.points.len() - 1];
+        self.points.splice(
+            start + 1..end,
+            inner.iter().map(|point| {
+                let mut point = *point;
+                point.set_x((point.pos().x + from).clamp(from, to));
+                point
+            }),
+        );
+        self.interpolations
+            .splice(first..=last, clip.interpolations.iter().copied());
+
+        // The ends of the pasted part take the values of the clip
+        let end = start + inner.len() + 1;
+        let (clip_start, clip_end) = (clip.points[0].pos(), clip.points[inner.len() + 1].pos());
+        self.points[start].set_pos(Pos2::new(from, clip_start.y));
+        self.points[end].set_pos(Pos2::new(to, clip_end.y));
+        if self.linked {
+            let last = self.points.len() - 1;
+            if start == 0 {
+                let pos = self.points[0].pos();
+                self.points[last].set_pos(pos);
+            } else if end == last {
+                let pos = self.points[last].pos();
+                self.points[0].set_pos(pos);
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn check_range(&self, from: f32, to: f32) -> Result<(), EditError> {
+        if from.is_finite() && to.is_finite() && 0.0 <= from && from < to && to <= self.length() {
+            Ok(())
+        } else {
+            Err(EditError::Range(from, to))
+        }
+    }
+
+    /// Anchors at `from` and `to`, the range must be inside the loop
+    pub(super) fn split_range(&mut self, from: f32, to: f32) -> (usize, usize) {
+        // Vertical jumps at the ends stay outside of the range
+        let start = self.split_at(from, true);
+        let end = self.split_at(to, false);
+        (start, end)
+    }
+
+    /// Indices of the first and the last segment between the anchors `start` and `end`
+    pub(super) fn segment_span(&self, start: usize, end: usize) -> (usize, usize) {
+        let segments = self.segments();
+        let first = segments
+            .iter()
+            .position(|(segment_start, _)| *segment_start == start)
+            .unwrap_or_default();
+        let last = segments
+            .iter()
+            .position(|(_, segment_end)| *segment_end == end)
+            .unwrap_or(first);
+        (first, last)
+    }
+
+    /// Index of an anchor at `x`, the segment there is split if needed
+    ///
+    /// Bezier segments keep their exact shape. Other interpolations keep their mode on both sides,
+    /// which is exact for linear, step and hold segments. At vertical jumps the last anchor at `x`
+    /// is used if `last` is set, otherwise the first one.
+    pub(super) fn split_at(&mut self, x: f32, last: bool) -> usize {
+        let mut anchors = (0..self.points.len())
+            .filter(|i| self.points[*i].is_anchor() && self.points[*i].pos().x == x);
+        let existing = if last { anchors.last() } else { anchors.next() };
+        if let Some(i) = existing {
+            return i;
+        }
+
+        let Some(segment) = self.segment_at(x) else {
+            return self.points.len() - 1;
+        };
+        let (start, end) = self.segments()[segment];
+        let interpolation = self.interpolations[segment];
+        let (from, to) = (self.points[start].pos(), self.points[end].pos());
+
+        let (left, anchor, right) = if interpolation == Interpolation::Bezier {
+            let controls: Vec<Pos2> = self.points[start..=end]
+                .iter()
+                .map(|point| point.pos())
+                .collect();
+            let (left, anchor, right) = de_casteljau(&controls, segment_t(&controls, x));
+            let anchor = Pos2::new(x, anchor.y);
+            // Against rounding errors breaking the order of the points
+            let clamp = |pos: Pos2, min: f32, max: f32| Pos2::new(pos.x.clamp(min, max), pos.y);
+            (
+                left.into_iter().map(|pos| clamp(pos, from.x, x)).collect(),
+                anchor,
+                right.into_iter().map(|pos| clamp(pos, x, to.x)).collect(),
+            )
+        } else {
+            let anchor = Pos2::new(x, self.y(x));
+            (
+                vec![from.lerp(anchor, 0.5)],
+                anchor,
+                vec![anchor.lerp(to, 0.5)],
+            )
+        };
+
+        let index = start + 1 + left.len();
+        self.points.splice(
+            start + 1..end,
+            left.into_iter()
+                .map(CurvePoint::Bezier)
+                .chain([CurvePoint::Inner(anchor)])
+                .chain(right.into_iter().map(CurvePoint::Bezier)),
+        );
+        self.interpolations.insert(segment, interpolation);
+        index
+    }
+}
+
+/// Splits a bezier at `t` into the bezier points of both halves and the point between them
+fn de_casteljau(controls: &[Pos2], t: f32) -> (Vec<Pos2>, Pos2, Vec<Pos2>) {
+    let mut levels = vec![controls.to_vec()];
+    while levels.last().map_or(0, Vec::len) > 1 {
+        let previous = levels.last().expect("At least one level");
+        let next = previous
+            .windows(2)
+            .map(|pair| pair[0].lerp(pair[1], t))
+            .collect();
+        levels.push(next);
+    }
+    let inner = &levels[1..levels.len() - 1];
+    let left = inner.iter().map(|level| level[0]).collect();
+    let right = inner
+        .iter()
+        .rev()
+        .map(|level| level[level.len() - 1])
+        .collect();
+    (left, levels[levels.len() - 1][0], right)
+}
@@ -0,0 +1,676 @@
+use super::{point::CurvePoint, Curve, Interpolation};
+use egui::{Pos2, Vec2};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt};
+
+/// A single change of a curve
+///
+/// The editor only changes curves through edits, so a recorded list of them can be replayed
+/// without a UI. Positions are in curve coordinates, beats on x and 0.0 (max) to 100.0 (min) on y.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "edit", rename_all = "snake_case")]
+pub enum Edit {
+    /// Splits the segment at `x` with a new inner anchor, without `y` it is put on the curve
+    InsertAnchor {
+        x: f32,
+        y: Option<f32>,
+    },
+    /// Merges the two segments around an inner anchor
+    RemoveAnchor {
+        index: usize,
+    },
+    /// Moves a point as far towards `pos` as its neighbours allow
+    MovePoint {
+        index: usize,
+        pos: Pos2,
+    },
+    /// Puts the bezier points of a segment on the line between its anchors
+    Straighten {
+        segment: usize,
+    },
+    /// Switches a segment between a quadratic and a cubic bezier
+    ToggleCubic {
+        segment: usize,
+    },
+    SetSmooth {
+        index: usize,
+        smooth: bool,
+    },
+    /// Linked first and last points always have the same value, linking moves the last point
+    SetLinked {
+        linked: bool,
+    },
+    SetInterpolation {
+        segment: usize,
+        interpolation: Interpolation,
+    },
+    /// Moves points together by `delta`, as far as the points around them allow
+    MovePoints {
+        indices: Vec<usize>,
+        delta: Vec2,
+    },
+    /// Scales the distances of points to `origin`, a negative `factor.y` flips the values
+    ScalePoints {
+        indices: Vec<usize>,
+        origin: Pos2,
+        factor: Vec2,
+    },
+    /// Reverses the part of the curve between the outermost anchors of the points in time
+    ReversePoints {
+        indices: Vec<usize>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EditError {
+    /// There is no point with this index
+    Point(usize),
+    /// There is no segment with this index
+    Segment(usize),
+    /// The point is not an inner anchor
+    NotInner(usize),
+    /// The position is not a finite number or not inside the loop
+    Position(f32, f32),
+    /// Scaling in time must keep the order of the points
+    Factor(f32, f32),
+    /// Values must scale by a finite factor that is not negative
+    ValueFactor(f32),
+    /// Stretching must keep the order of the points and leave some of the loop after the range
+    Stretch(f32),
+    /// The beat range is empty or not inside the loop
+    Range(f32, f32),
+    /// An amount to change the curve by is infinite or NaN
+    NotFinite(f32),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::Point(index) => write!(f, "There is no point {index}"),
+            EditError::Segment(segment) => write!(f, "There is no segment {segment}"),
+            EditError::NotInner(index) => write!(f, "Point {index} is not an inner anchor"),
+            EditError::Position(x, y) => write!(f, "Position ({x}, {y}) is not inside the loop"),
+            EditError::Range(from, to) => {
+                write!(f, "Beat range {from}..{to} is empty or not inside the loop")
+            }
+            EditError::Factor(x, y) => {
+                write!(
+                    f,
+                    "Invalid scale factor ({x}, {y}), time must scale by a positive factor"
+                )
+            }
+            EditError::ValueFactor(factor) => write!(
+                f,
+                "Invalid value factor {factor}, values must scale by a finite factor of at least 0"
+            ),
+            EditError::Stretch(factor) => write!(
+                f,
+                "Can not stretch by {factor}, some of the loop must be left after the range"
+            ),
+            EditError::NotFinite(amount) => write!(f, "{amount} is not a finite number"),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl Curve {
+    pub fn apply(&mut self, edit: &Edit) -> Result<(), EditError> {
+        match *edit {
+            Edit::InsertAnchor { x, y } => self.insert_anchor(x, y).map(|_| ()),
+            Edit::RemoveAnchor { index } => self.remove_anchor(index),
+            Edit::MovePoint { index, pos } => self.move_point(index, pos),
+            Edit::Straighten { segment } => self.straighten(segment),
+            Edit::ToggleCubic { segment } => self.toggle_cubic(segment),
+            Edit::SetSmooth { index, smooth } => self.set_smooth(index, smooth),
+            Edit::SetLinked { linked } => {
+                self.set_linked(linked);
+                Ok(())
+            }
+            Edit::SetInterpolation {
+                segment,
+                interpolation,
+            } => self.set_interpolation(segment, interpolation),
+            Edit::MovePoints { ref indices, delta } => self.move_points(indices, delta),
+            Edit::ScalePoints {
+                ref indices,
+                origin,
+                factor,
+            } => self.scale_points(indices, origin, factor),
+            Edit::ReversePoints { ref indices } => self.reverse_points(indices),
+        }
+    }
+
+    /// Returns the index of the new anchor, both new segments start as lines with the
+    /// interpolation of the old one
+    pub fn insert_anchor(&mut self, x: f32, y: Option<f32>) -> Result<usize, EditError> {
+        let y = y.unwrap_or_else(|| self.y(x));
+        if !x.is_finite() || !y.is_finite() || !(0.0..=100.0).contains(&y) {
+            return Err(EditError::Position(x, y));
+        }
+        let segment = self
+            .segment_at(x)
+            .filter(|_| x >= 0.0)
+            .ok_or(EditError::Position(x, y))?;
+
+        let (start, end) = self.segments()[segment];
+        let pos = Pos2::new(x, y);
+        let (before, after) = (self.points[start].pos(), self.points[end].pos());
+        self.points.splice(
+            start + 1..end,
+            [
+                CurvePoint::Bezier(before.lerp(pos, 0.5)),
+                CurvePoint::Inner(pos),
+                CurvePoint::Bezier(pos.lerp(after, 0.5)),
+            ],
+        );
+        self.interpolations
+            .insert(segment, self.interpolations[segment]);
+        Ok(start + 2)
+    }
+
+    /// The merged segment is a line with the interpolation of the left segment
+    pub fn remove_anchor(&mut self, index: usize) -> Result<(), EditError> {
+        self.inner_anchor(index)?;
+
+        let segments = self.segments();
+        let right = segments
+            .iter()
+            .position(|(start, _)| *start == index)
+            .ok_or(EditError::NotInner(index))?;
+        let (start, end) = (segments[right - 1].0, segments[right].1);
+        let (before, after) = (self.points[start].pos(), self.points[end].pos());
+        self.points.splice(
+            start + 1..end,
+            [CurvePoint::Bezier(before.lerp(after, 0.5))],
+        );
+        self.interpolations.remove(right);
+        Ok(())
+    }
+
+    /// Points stay between their neighbours and inside the loop, first and last points only
+    /// change their value
+    ///
+    /// Smooth anchors take their bezier points along, bezier points next to a smooth anchor turn
+    /// the opposite one.
+    pub fn move_point(&mut self, index: usize, pos: Pos2) -> Result<(), EditError> {
+        let point = *self.points.get(index).ok_or(EditError::Point(index))?;
+        if !pos.x.is_finite() || !pos.y.is_finite() {
+            return Err(EditError::Position(pos.x, pos.y));
+        }
+
+        let old_pos = point.pos();
+        let mut pos = pos;
+        if !point.is_outer() {
+            pos.x = pos.x.clamp(
+                self.points[index - 1].pos().x,
+                self.points[index + 1].pos().x,
+            );
+        }
+        pos.y = pos.y.clamp(0.0, 100.0);
+        self.points[index].set_pos(pos);
+        let delta = self.points[index].pos() - old_pos;
+        if delta == Vec2::ZERO {
+            return Ok(());
+        }
+
+        if point.is_outer() && self.linked {
+            let mirrored = self.points.len() - index - 1;
+            self.points[mirrored].set_pos(pos);
+        } else if point.is_smooth() {
+            for handle in [index - 1, index + 1] {
+                let mut pos = self.points[handle].pos() + delta;
+                pos.x = pos.x.clamp(
+                    self.points[handle - 1].pos().x,
+                    self.points[handle + 1].pos().x,
+                );
+                pos.y = pos.y.clamp(0.0, 100.0);
+                self.points[handle].set_pos(pos);
+            }
+        } else if point.is_bezier() {
+            for anchor in [index - 1, index + 1] {
+                if self.points[anchor].is_smooth() {
+                    self.smooth(anchor, Some(index));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn straighten(&mut self, segment: usize) -> Result<(), EditError> {
+        let (start, end) = self.segment(segment)?;
+        let (from, to) = (self.points[start].pos(), self.points[end].pos());
+        let steps = (end - start) as f32;
+        for (k, point) in self.points[start + 1..end].iter_mut().enumerate() {
+            point.set_pos(from.lerp(to, (k + 1) as f32 / steps));
+        }
+        Ok(())
+    }
+
+    pub fn toggle_cubic(&mut self, segment: usize) -> Result<(), EditError> {
+        let (start, end) = self.segment(segment)?;
+        let (from, to) = (self.points[start].pos(), self.points[end].pos());
+        let controls = if end - start == 2 {
+            // Degree elevation keeps exactly the same shape
+            let control = self.points[start + 1].pos();
+            vec![
+                CurvePoint::Bezier(from + (control - from) * 2.0 / 3.0),
+                CurvePoint::Bezier(to + (control - to) * 2.0 / 3.0),
+            ]
+        } else {
+            // Closest quadratic to the cubic
+            let first = self.points[start + 1].pos().to_vec2();
+            let second = self.points[start + 2].pos().to_vec2();
+            let control = ((first + second) * 3.0 - from.to_vec2() - to.to_vec2()) / 4.0;
+            vec![CurvePoint::Bezier(Pos2::new(
+                control.x.clamp(from.x, to.x),
+                control.y.clamp(0.0, 100.0),
+            ))]
+        };
+        self.points.splice(start + 1..end, controls);
+        Ok(())
+    }
+
+    pub fn set_smooth(&mut self, index: usize, smooth: bool) -> Result<(), EditError> {
+        let pos = self.inner_anchor(index)?;
+        self.points[index] = if smooth {
+            CurvePoint::Smooth(pos)
+        } else {
+            CurvePoint::Inner(pos)
+        };
+        if smooth {
+            self.smooth(index, None);
+        }
+        Ok(())
+    }
+
+    pub fn set_linked(&mut self, linked: bool) {
+        self.linked = linked;
+        if let (true, Some(first)) = (linked, self.points.first().map(CurvePoint::pos)) {
+            if let Some(last) = self.points.last_mut() {
+                last.set_pos(first);
+            }
+        }
+    }
+
+    pub fn set_interpolation(
+        &mut self,
+        segment: usize,
+        interpolation: Interpolation,
+    ) -> Result<(), EditError> {
+        let current = self
+            .interpolations
+            .get_mut(segment)
+            .ok_or(EditError::Segment(segment))?;
+        *current = interpolation;
+        Ok(())
+    }
+
+    pub fn move_points(&mut self, indices: &[usize], delta: Vec2) -> Result<(), EditError> {
+        if !delta.x.is_finite() || !delta.y.is_finite() {
+            return Err(EditError::Position(delta.x, delta.y));
+        }
+        let moving = self.moving_points(indices)?;
+
+        // Every point limits how far the whole group can go
+        let mut min = Vec2::splat(f32::NEG_INFINITY);
+        let mut max = Vec2::splat(f32::INFINITY);
+        for &i in &moving {
+            let pos = self.points[i].pos();
+            if self.points[i].is_outer() {
+                min.x = min.x.max(0.0);
+                max.x = max.x.min(0.0);
+            } else {
+                if !moving.contains(&(i - 1)) {
+                    min.x = min.x.max(self.points[i - 1].pos().x - pos.x);
+                }
+                if !moving.contains(&(i + 1)) {
+                    max.x = max.x.min(self.points[i + 1].pos().x - pos.x);
+                }
+            }
+            min.y = min.y.max(-pos.y);
+            max.y = max.y.min(100.0 - pos.y);
+        }
+        let delta = delta.max(min).min(max);
+
+        for &i in &moving {
+            let pos = self.points[i].pos() + delta;
+            self.points[i].set_pos(pos);
+        }
+        self.group_moved(&moving);
+        Ok(())
+    }
+
+    /// Points are stopped by the points around the group, so their order never changes
+    pub fn scale_points(
+        &mut self,
+        indices: &[usize],
+        origin: Pos2,
+        factor: Vec2,
+    ) -> Result<(), EditError> {
+        if !(factor.x.is_finite() && factor.x > 0.0 && factor.y.is_finite()) {
+            return Err(EditError::Factor(factor.x, factor.y));
+        }
+        if !origin.x.is_finite() || !origin.y.is_finite() {
+            return Err(EditError::Position(origin.x, origin.y));
+        }
+        let moving = self.moving_points(indices)?;
+
+        // Outer points never move in time, so they always stop the others
+        let fixed = |i: usize| !moving.contains(&i) || self.points[i].is_outer();
+        let limits: Vec<(f32, f32)> = moving
+            .iter()
+            .map(|&i| {
+                let before = (0..i).rev().find(|&j| fixed(j));
+                let after = (i + 1..self.points.len()).find(|&j| fixed(j));
+                (
+                    before.map_or(0.0, |j| self.points[j].pos().x),
+                    after.map_or(self.length(), |j| self.points[j].pos().x),
+                )
+            })
+            .collect();
+
+        for (&i, (min_x, max_x)) in moving.iter().zip(limits) {
+            let pos = origin + (self.points[i].pos() - origin) * factor;
+            self.points[i].set_pos(Pos2::new(
+                pos.x.clamp(min_x, max_x),
+                pos.y.clamp(0.0, 100.0),
+            ));
+        }
+        self.group_moved(&moving);
+        Ok(())
+    }
+
+    /// Bezier segments keep their exact shape, step and hold segments swap
+    ///
+    /// The other interpolations are symmetric, except for exponential segments which keep easing
+    /// in.
+    pub fn reverse_points(&mut self, indices: &[usize]) -> Result<(), EditError> {
+        let moving = self.moving_points(indices)?;
+        let (Some(&first), Some(&last)) = (moving.first(), moving.last()) else {
+            return Ok(());
+        };
+        let start = (0..=first)
+            .rev()
+            .find(|&i| self.points[i].is_anchor())
+            .unwrap_or(0);
+        let end = (last..self.points.len())
+            .find(|&i| self.points[i].is_anchor())
+            .unwrap_or(self.points.len() - 1);
+        self.reverse_span(start, end);
+        Ok(())
+    }
+
+    /// Mirrors the points between the anchors `start` and `end` in time
+    pub(super) fn reverse_span(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let (from, to) = (self.points[start], self.points[end]);
+        let mirror = from.pos().x + to.pos().x;
+        self.points[start..=end].reverse();
+        for point in &mut self.points[start..=end] {
+            point.set_x(mirror - point.pos().x);
+        }
+        // First and last stay where they are, only their values change
+        let (new_start, new_end) = (self.points[start].pos(), self.points[end].pos());
+        self.points[start] = from;
+        self.points[start].set_pos(new_start);
+        self.points[end] = to;
+        self.points[end].set_pos(new_end);
+
+        let segments = self.segments();
+        let first = segments.iter().position(|(s, _)| *s == start);
+        let last = segments.iter().position(|(_, e)| *e == end);
+        if let (Some(first), Some(last)) = (first, last) {
+            self.interpolations[first..=last].reverse();
+            for interpolation in &mut self.interpolations[first..=last] {
+                *interpolation = match *interpolation {
+                    Interpolation::Step => Interpolation::Hold,
+                    Interpolation::Hold => Interpolation::Step,
+                    interpolation => interpolation,
+                };
+            }
+        }
+    }
+
+    /// Valid `indices` plus the bezier points of smooth anchors among them
+    fn moving_points(&self, indices: &[usize]) -> Result<BTreeSet<usize>, EditError> {
+        let mut moving = BTreeSet::new();
+        for &i in indices {
+            let point = self.points.get(i).ok_or(EditError::Point(i))?;
+            moving.insert(i);
+            if point.is_smooth() {
+                moving.extend([i - 1, i + 1]);
+            }
+        }
+        Ok(moving)
+    }
+
+    /// Keeps linked outer points and smooth anchors next to a moved group intact
+    fn group_moved(&mut self, moving: &BTreeSet<usize>) {
+        let last = self.points.len() - 1;
+        if self.linked {
+            if moving.contains(&0) {
+                let pos = self.points[0].pos();
+                self.points[last].set_pos(pos);
+            } else if moving.contains(&last) {
+                let pos = self.points[last].pos();
+                self.points[0].set_pos(pos);
+            }
+        }
+        for &i in moving {
+            if !self.points[i].is_bezier() {
+                continue;
+            }
+            for anchor in [i - 1, i + 1] {
+                if self.points[anchor].is_smooth() && !moving.contains(&anchor) {
+                    self.smooth(anchor, Some(i));
+                }
+            }
+        }
+    }
+
+    fn segment(&self, segment: usize) -> Result<(usize, usize), EditError> {
+        self.segments()
+            .get(segment)
+            .copied()
+            .ok_or(EditError::Segment(segment))
+    }
+
+    fn inner_anchor(&self, index: usize) -> Result<Pos2, EditError> {
+        match self.points.get(index) {
+            Some(point) if point.is_inner() => Ok(point.pos()),
+            Some(_) => Err(EditError::NotInner(index)),
+            None => Err(EditError::Point(index)),
+        }
+    }
+
+    /// Puts the bezier points around the smooth anchor `i` on one line, `keep` is not moved
+    fn smooth(&mut self, i: usize, keep: Option<usize>) {
+        if i == 0 || i + 1 >= self.points.len() {
+            return;
+        }
+        let (before, after) = (i - 1, i + 1);
+        let anchor = self.points[i].pos();
+        let direction = match keep {
+            Some(keep) if keep == before => anchor - self.points[before].pos(),
+            Some(keep) if keep == after => self.points[after].pos() - anchor,
+            _ => self.points[after].pos() - self.points[before].pos(),
+        }
+        .normalized();
+        if direction == Vec2::ZERO {
+            return;
+        }
+
+        for (handle, sign) in [(before, -1.0), (after, 1.0)] {
+            if Some(handle) == keep {
+                continue;
+            }
+            let distance = (self.points[handle].pos() - anchor).length();
+            let mut pos = anchor + direction * sign * distance;
+            pos.x = pos.x.clamp(
+                self.points[handle - 1].pos().x,
+                self.points[handle + 1].pos().x,
+            );
+            pos.y = pos.y.clamp(0.0, 100.0);
+            self.points[handle].set_pos(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replays `edits` one after another like a recorded editing session
+    fn replay(curve: &mut Curve, edits: &[Edit]) -> Result<(), EditError> {
+        edits.iter().try_for_each(|edit| curve.apply(edit))
+    }
+
+    #[test]
+    fn replaying_recorded_edits() {
+        let edits = vec![
+            Edit::InsertAnchor { x: 0.5, y: None },
+            Edit::MovePoint {
+                index: 2,
+                pos: Pos2::new(0.5, 20.0),
+            },
+            Edit::ToggleCubic { segment: 1 },
+            Edit::SetSmooth {
+                index: 2,
+                smooth: true,
+            },
+            Edit::SetInterpolation {
+                segment: 2,
+                interpolation: Interpolation::Step,
+            },
+            Edit::Straighten { segment: 0 },
+            Edit::RemoveAnchor { index: 5 },
+        ];
+        let mut curve = Curve::alternating();
+        replay(&mut curve, &edits).unwrap();
+
+        assert_eq!(curve.position(2), Some(Pos2::new(0.5, 20.0)));
+        assert!(curve.points[2].is_smooth());
+        // The bezier point of the straightened first segment is on the line again
+        assert_eq!(curve.position(1), Some(Pos2::new(0.25, 60.0)));
+        assert_eq!(curve.interpolations.len(), 4);
+        assert_eq!(curve.interpolation(2), Some(Interpolation::Bezier));
+
+        // Edits survive being stored, replaying them again gives the same curve
+        let json = serde_json::to_string(&edits).unwrap();
+        let stored: Vec<Edit> = serde_json::from_str(&json).unwrap();
+        let mut replayed = Curve::alternating();
+        replay(&mut replayed, &stored).unwrap();
+        assert_eq!(replayed, curve);
+    }
+
+    #[test]
+    fn invalid_edits_leave_the_curve_unchanged() {
+        let original = Curve::alternating();
+        let last = original.points.len() - 1;
+        let cases = [
+            (Edit::RemoveAnchor { index: 0 }, EditError::NotInner(0)),
+            (
+                Edit::RemoveAnchor { index: last },
+                EditError::NotInner(last),
+            ),
+            (Edit::RemoveAnchor { index: 1 }, EditError::NotInner(1)),
+            (Edit::RemoveAnchor { index: 99 }, EditError::Point(99)),
+            (
+                Edit::MovePoint {
+                    index: 99,
+                    pos: Pos2::ZERO,
+                },
+                EditError::Point(99),
+            ),
+            (
+                Edit::MovePoint {
+                    index: 2,
+                    pos: Pos2::new(f32::NAN, 0.0),
+                },
+                EditError::Position(f32::NAN, 0.0),
+            ),
+            (Edit::Straighten { segment: 4 }, EditError::Segment(4)),
+            (Edit::ToggleCubic { segment: 4 }, EditError::Segment(4)),
+            (
+                Edit::InsertAnchor {
+                    x: 5.0,
+                    y: Some(50.0),
+                },
+                EditError::Position(5.0, 50.0),
+            ),
+            (
+                Edit::InsertAnchor {
+                    x: 1.0,
+                    y: Some(150.0),
+                },
+                EditError::Position(1.0, 150.0),
+            ),
+            (
+                Edit::SetSmooth {
+                    index: 0,
+                    smooth: true,
+                },
+                EditError::NotInner(0),
+            ),
+            (
+                Edit::SetInterpolation {
+                    segment: 4,
+                    interpolation: Interpolation::Linear,
+                },
+                EditError::Segment(4),
+            ),
+        ];
+        for (edit, expected) in cases {
+            let mut curve = original.clone();
+            let error = curve.apply(&edit).unwrap_err();
+            // NaN never equals itself
+            assert_eq!(format!("{error:?}"), format!("{expected:?}"), "{edit:?}");
+            assert_eq!(curve, original, "{edit:?}");
+        }
+    }
+
+    #[test]
+    fn moved_points_stay_between_their_neighbours() {
+        let mut curve = Curve::alternating();
+        // The anchor at beat 1 is stopped by its bezier points at 0.5 and 1.5
+        curve.move_point(2, Pos2::new(3.5, -20.0)).unwrap();
+        assert_eq!(curve.position(2), Some(Pos2::new(1.5, 0.0)));
+        curve.move_point(2, Pos2::new(-1.0, 120.0)).unwrap();
+        assert_eq!(curve.position(2), Some(Pos2::new(0.5, 100.0)));
+
+        // First and last points only change their value
+        curve.move_point(0, Pos2::new(2.0, 40.0)).unwrap();
+        assert_eq!(curve.position(0), Some(Pos2::new(0.0, 40.0)));
+
+        // Groups keep their order
+        curve.move_points(&[3, 4], Vec2::new(10.0, 0.0)).unwrap();
+        assert_eq!(curve.position(4).map(|pos| pos.x), Some(2.5));
+        assert!(curve
+            .points
+            .windows(2)
+            .all(|pair| pair[0].pos().x <= pair[1].pos().x));
+    }
+
+    #[test]
+    fn linked_endpoints_share_their_value() {
+        let mut curve = Curve::alternating();
+        let last = curve.points.len() - 1;
+        assert!(curve.linked());
+
+        curve.move_point(0, Pos2::new(0.0, 30.0)).unwrap();
+        assert_eq!(curve.position(last), Some(Pos2::new(4.0, 30.0)));
+        curve.move_points(&[last], Vec2::new(0.0, 10.0)).unwrap();
+        assert_eq!(curve.position(0), Some(Pos2::new(0.0, 40.0)));
+
+        curve.apply(&Edit::SetLinked { linked: false }).unwrap();
+        curve.move_point(0, Pos2::new(0.0, 70.0)).unwrap();
+        assert_eq!(curve.position(last), Some(Pos2::new(4.0, 40.0)));
+
+        // Linking again moves the last point to the value of the first one
+        curve.apply(&Edit::SetLinked { linked: true }).unwrap();
+        assert_eq!(curve.position(last), Some(Pos2::new(4.0, 70.0)));
+    }
+}
@@ -0,0 +1,402 @@
+use super::{Curve, Interpolation};
+use egui::Pos2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How beat positions outside of the loop are evaluated
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutOfRange {
+    /// Positions before the loop get the first value, positions after it the last one
+    Clamp,
+    /// The loop repeats endlessly
+    #[default]
+    Wrap,
+    /// The loop plays forward and backward in turns
+    PingPong,
+    /// Outside of the loop the last value is held, e.g. for one shot curves which have ended
+    ///
+    /// Positions before the loop get the last value as well, unlike `Clamp` the direction of the
+    /// overrun is ignored. Use `Clamp` to hold the first value before playback starts.
+    HoldLast,
+    /// Positions outside of the loop are an error
+    Error,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EvaluateError {
+    OutOfRange { beat_position: f32, length: f32 },
+    NotFinite(f32),
+}
+
+impl fmt::Display for EvaluateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvaluateError::OutOfRange {
+                beat_position,
+                length,
+            } => write!(
+                f,
+                "Beat position out of range 0.0..={length}: {beat_position}"
+            ),
+            EvaluateError::NotFinite(beat_position) => {
+                write!(f, "Beat position is not a finite number: {beat_position}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvaluateError {}
+
+impl Curve {
+    /// Output value in the curve's range, positions outside of the loop wrap around
+    ///
+    /// Never panics, a position that is not a finite number evaluates to the start of the loop.
+    pub fn value(&self, beat_position: f32) -> f32 {
+        self.try_value(beat_position, OutOfRange::Wrap)
+            .unwrap_or_else(|_| self.y_to_value(self.y(0.0)))
+    }
+
+    pub fn try_value(
+        &self,
+        beat_position: f32,
+        out_of_range: OutOfRange,
+    ) -> Result<f32, EvaluateError> {
+        self.position_in_loop(beat_position, out_of_range)
+            .map(|beat_position| self.y_to_value(self.y(beat_position)))
+    }
+
+    /// Maps `beat_position` into `0.0..=length` according to `out_of_range`
+    pub fn position_in_loop(
+        &self,
+        beat_position: f32,
+        out_of_range: OutOfRange,
+    ) -> Result<f32, EvaluateError> {
+        if !beat_position.is_finite() {
+            return Err(EvaluateError::NotFinite(beat_position));
+        }
+
+        let length = self.length();
+        if (0.0..=length).contains(&beat_position) {
+            return Ok(beat_position);
+        }
+
+        match out_of_range {
+            OutOfRange::Clamp => Ok(beat_position.clamp(0.0, length)),
+            OutOfRange::Wrap => Ok(self.wrap(beat_position)),
+            OutOfRange::PingPong => {
+                let position = beat_position.rem_euclid(2.0 * length);
+                Ok(if position > length {
+                    2.0 * length - position
+                } else {
+                    position
+                })
+            }
+            OutOfRange::HoldLast => Ok(length),
+            OutOfRange::Error => Err(EvaluateError::OutOfRange {
+                beat_position,
+                length,
+            }),
+        }
+    }
+
+    /// Wraps any finite `beat_position` into `0.0..=length`
+    fn wrap(&self, beat_position: f32) -> f32 {
+        // rem_euclid can round up to exactly `length` for tiny negative positions, which is
+        // still a valid position
+        beat_position.rem_euclid(self.length())
+    }
+
+    /// Raw y coordinate of the curve at `beat_position` in `0.0..=length`
+    ///
+    /// At vertical jumps, where anchors share the same x, the value after the jump is used.
+    pub(super) fn y(&self, beat_position: f32) -> f32 {
+        self.segments()
+            .into_iter()
+            .zip(&self.interpolations)
+            .find(|((_, end), _)| self.points[*end].pos().x > beat_position)
+            .map(|((start, end), interpolation)| {
+                let (from, to) = (self.points[start].pos(), self.points[end].pos());
+                match interpolation {
+                    Interpolation::Bezier => {
+                        let segment: Vec<Pos2> = self.points[start..=end]
+                            .iter()
+                            .map(|point| point.pos())
+                            .collect();
+                        bezier_y(&segment, beat_position)
+                    }
+                    interpolation => {
+                        let t = ((beat_position - from.x) / (to.x - from.x)).clamp(0.0, 1.0);
+                        emath::lerp(from.y..=to.y, interpolation.ease(t))
+                    }
+                }
+            })
+            // At the very end of the loop
+            .unwrap_or_else(|| self.points.last().map_or(0.0, |point| point.pos().y))
+    }
+}
+
+/// Exact y of a quadratic or cubic bezier segment at `x`
+///
+/// Requires the x coordinates of the points to be ordered, which makes x(t) monotonic so there is
+/// exactly one `t` in `0.0..=1.0` for every x in the segment.
+fn bezier_y(segment: &[Pos2], x: f32) -> f32 {
+    let ys: Vec<f64> = segment.iter().map(|pos| pos.y as f64).collect();
+    bernstein(&ys, segment_t(segment, x) as f64) as f32
+}
+
+/// Parameter of a quadratic or cubic bezier segment at `x`
+pub(super) fn segment_t(segment: &[Pos2], x: f32) -> f32 {
+    let xs: Vec<f64> = segment.iter().map(|pos| pos.x as f64).collect();
+    bezier_t(&xs, x as f64) as f32
+}
+
+/// Evaluates a bezier polynomial of degree 2 or 3 at `t`
+fn bernstein(p: &[f64], t: f64) -> f64 {
+    let u = 1.0 - t;
+    match p {
+        [p0, p1, p2] => u * u * p0 + 2.0 * u * t * p1 + t * t * p2,
+        [p0, p1, p2, p3] => {
+            u * u * u * p0 + 3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t * p3
+        }
+        _ => unreachable!("Segments have one or two bezier points"),
+    }
+}
+
+/// Solves x(t) = `x` for the bezier's parameter `t`
+fn bezier_t(xs: &[f64], x: f64) -> f64 {
+    let scale = (xs[xs.len() - 1] - xs[0]).abs();
+    if scale <= f64::EPSILON {
+        // Vertical jump, take the value after it
+        return 1.0;
+    }
+
+    // Power basis coefficients of x(t) - x, highest degree first
+    let coefficients = match xs {
+        [x0, x1, x2] => vec![x0 - 2.0 * x1 + x2, 2.0 * (x1 - x0), x0 - x],
+        [x0, x1, x2, x3] => vec![
+            -x0 + 3.0 * x1 - 3.0 * x2 + x3,
+            3.0 * x0 - 6.0 * x1 + 3.0 * x2,
+            3.0 * (x1 - x0),
+            x0 - x,
+        ],
+        _ => unreachable!("Segments have one or two bezier points"),
+    };
+
+    // The root in 0.0..=1.0 is the one with the smallest residual after clamping
+    let residual = |t: f64| polynomial(&coefficients, t.clamp(0.0, 1.0)).0.abs();
+    let t = roots(&coefficients, scale)
+        .into_iter()
+        .filter(|t| !t.is_nan())
+        .min_by(|l, r| residual(*l).total_cmp(&residual(*r)))
+        .unwrap_or(0.0);
+    polish(&coefficients, t.clamp(0.0, 1.0)).clamp(0.0, 1.0)
+}
+
+/// Real roots of a polynomial up to degree 3, leading coefficients that are negligible compared
+/// to `scale` are dropped
+fn roots(coefficients: &[f64], scale: f64) -> Vec<f64> {
+    match coefficients {
+        [a, rest @ ..] if a.abs() <= 1e-9 * scale => roots(rest, scale),
+        [b, c] => vec![-c / b],
+        [a, b, c] => {
+            // Numerically stable form of the quadratic formula
+            let discriminant = (b * b - 4.0 * a * c).max(0.0);
+            let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+            if q == 0.0 {
+                vec![0.0]
+            } else {
+                vec![q / a, c / q]
+            }
+        }
+        [a, b, c, d] => {
+            // Cardano on the depressed cubic t = s - b / 3
+            let (b, c, d) = (b / a, c / a, d / a);
+            let p = c - b * b / 3.0;
+            let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+            let discriminant = q * q / 4.0 + p * p * p / 27.0;
+            let shift = b / 3.0;
+            if discriminant >= 0.0 {
+                let sqrt = discriminant.sqrt();
+                let sum = (-q / 2.0 + sqrt).cbrt() + (-q / 2.0 - sqrt).cbrt();
+                // The second one is only a root for a discriminant of zero, i.e. a double root
+                vec![sum - shift, -sum / 2.0 - shift]
+            } else {
+                let r = 2.0 * (-p / 3.0).sqrt();
+                let phi = (3.0 * q / (p * r)).clamp(-1.0, 1.0).acos() / 3.0;
+                (0..3)
+                    .map(|k| r * (phi - 2.0 * std::f64::consts::PI * k as f64 / 3.0).cos() - shift)
+                    .collect()
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// A few Newton steps against rounding errors of the closed form solutions
+fn polish(coefficients: &[f64], mut t: f64) -> f64 {
+    for _ in 0..2 {
+        let (value, derivative) = polynomial(coefficients, t);
+        if derivative.abs() <= f64::EPSILON {
+            break;
+        }
+        let next = t - value / derivative;
+        if polynomial(coefficients, next).0.abs() >= value.abs() {
+            break;
+        }
+        t = next;
+    }
+    t
+}
+
+/// Value and derivative of a polynomial at `t` using Horner's method
+fn polynomial(coefficients: &[f64], t: f64) -> (f64, f64) {
+    coefficients
+        .iter()
+        .fold((0.0, 0.0), |(value, derivative), c| {
+            (value * t + c, derivative * t + value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::point::CurvePoint;
+
+    /// Allowed difference to the sampled values, in the unit of the default range `0.0..=100.0`
+    const TOLERANCE: f32 = 1e-3;
+
+    /// Curve of a single bezier segment through `controls`, spanning the whole loop
+    fn segment(controls: &[(f32, f32)]) -> Curve {
+        let mut curve = Curve::from_points(false, vec![(0.0, 0.0), (4.0, 0.0)]);
+        let last = controls.len() - 1;
+        curve.points = controls
+            .iter()
+            .enumerate()
+            .map(|(i, (x, y))| {
+                let pos = Pos2::new(*x, *y);
+                match i {
+                    0 => CurvePoint::First(pos),
+                    i if i == last => CurvePoint::Last(pos),
+                    _ => CurvePoint::Bezier(pos),
+                }
+            })
+            .collect();
+        curve.interpolations = vec![Interpolation::Bezier];
+        curve
+    }
+
+    /// Compares the curve to points sampled densely along the parameter of its only segment
+    fn assert_matches_samples(curve: &Curve) {
+        let xs: Vec<f64> = curve
+            .points
+            .iter()
+            .map(|point| point.pos().x as f64)
+            .collect();
+        let ys: Vec<f64> = curve
+            .points
+            .iter()
+            .map(|point| point.pos().y as f64)
+            .collect();
+        for i in 0..=10_000 {
+            let t = i as f64 / 10_000.0;
+            let (x, y) = (bernstein(&xs, t) as f32, bernstein(&ys, t) as f32);
+            let expected = curve.y_to_value(y);
+            let value = curve.value(x);
+            // Rounding x to f32 changes the value by the slope of the curve there, which gets
+            // steep where x(t) is flat
+            let (before, after) = ((t - 1e-6).max(0.0), (t + 1e-6).min(1.0));
+            let dx = bernstein(&xs, after) - bernstein(&xs, before);
+            let dy = bernstein(&ys, after) - bernstein(&ys, before);
+            let rounding = (dy / dx).abs() as f32 * f32::EPSILON * 4.0 * 2.0;
+            if !rounding.is_finite() {
+                continue;
+            }
+            assert!(
+                (value - expected).abs() < TOLERANCE + rounding,
+                "t = {t}, x = {x}: {value} instead of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn quadratic_matches_samples() {
+        assert_matches_samples(&segment(&[(0.0, 100.0), (1.3, 0.0), (4.0, 60.0)]));
+        assert_matches_samples(&segment(&[(0.0, 20.0), (3.9, 90.0), (4.0, 10.0)]));
+    }
+
+    #[test]
+    fn cubic_matches_samples() {
+        assert_matches_samples(&segment(&[
+            (0.0, 100.0),
+            (1.0, 0.0),
+            (3.0, 100.0),
+            (4.0, 0.0),
+        ]));
+        assert_matches_samples(&segment(&[
+            (0.0, 50.0),
+            (0.2, 0.0),
+            (0.4, 100.0),
+            (4.0, 50.0),
+        ]));
+    }
+
+    #[test]
+    fn degenerate_bezier_points_match_samples() {
+        // Bezier points on top of the anchors make x(t) flat at the ends
+        assert_matches_samples(&segment(&[(0.0, 100.0), (0.0, 0.0), (4.0, 50.0)]));
+        assert_matches_samples(&segment(&[(0.0, 100.0), (4.0, 0.0), (4.0, 50.0)]));
+        assert_matches_samples(&segment(&[
+            (0.0, 100.0),
+            (0.0, 0.0),
+            (4.0, 100.0),
+            (4.0, 0.0),
+        ]));
+        // Both bezier points in the middle, x(t) has a saddle there
+        assert_matches_samples(&segment(&[
+            (0.0, 100.0),
+            (2.0, 0.0),
+            (2.0, 100.0),
+            (4.0, 0.0),
+        ]));
+    }
+
+    #[test]
+    fn linear_x_matches_samples() {
+        // Evenly spaced x drops the higher degrees of x(t)
+        assert_matches_samples(&segment(&[(0.0, 100.0), (2.0, 0.0), (4.0, 70.0)]));
+        assert_matches_samples(&segment(&[
+            (0.0, 100.0),
+            (4.0 / 3.0, 0.0),
+            (8.0 / 3.0, 100.0),
+            (4.0, 30.0),
+        ]));
+    }
+
+    #[test]
+    fn vertical_jump_takes_the_value_after_it() {
+        let curve = Curve::forward();
+        // Every beat ramps up to the maximum and jumps back down to the minimum
+        assert!((curve.value(0.5) - 50.0).abs() < TOLERANCE);
+        assert!((curve.value(0.999) - 99.9).abs() < TOLERANCE * 10.0);
+        assert_eq!(curve.value(1.0), 0.0);
+        assert_eq!(curve.value(3.0), 0.0);
+        // The very end of the loop has the value of the last point
+        assert_eq!(curve.value(4.0), 100.0);
+
+        // A segment without width at all
+        let curve = segment(&[(2.0, 100.0), (2.0, 50.0), (2.0, 0.0)]);
+        assert_eq!(curve.y(2.0), 0.0);
+    }
+
+    #[test]
+    fn out_of_range_policies() {
+        let curve = Curve::forward();
+        let position = |beat, policy| curve.position_in_loop(beat, policy);
+        assert_eq!(position(-1.0, OutOfRange::Clamp), Ok(0.0));
+        assert_eq!(position(5.0, OutOfRange::Wrap), Ok(1.0));
+        assert_eq!(position(5.0, OutOfRange::PingPong), Ok(3.0));
+        assert_eq!(position(-1.0, OutOfRange::HoldLast), Ok(4.0));
+        assert!(position(5.0, OutOfRange::Error).is_err());
+        assert!(position(f32::NAN, OutOfRange::Wrap).is_err());
+    }
+}
@@ -0,0 +1,446 @@
+use super::{
+    super::preset, clip, point::CurvePoint, Curve, Interpolation, TimeSignature, ValueRange,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, path::Path};
+
+/// Identifies a curve document, so other JSON files are rejected early
+const FORMAT: &str = "ui_experiments/curve";
+/// Bump whenever the document layout changes and migrate older documents in `Curve::try_from`
+const VERSION: u32 = 5;
+
+/// On disk representation of a [`Curve`]
+///
+/// Version history:
+/// 1. Initial version, always one bar of 4/4
+/// 2. Added `time_signature` and `bars`
+/// 3. Added `range`, before all curves had values from 0 to 100
+/// 4. Added `smooth` points and cubic segments with two bezier points
+/// 5. Added `interpolations`, before all segments were beziers
+#[derive(Serialize, Deserialize)]
+pub struct CurveFile {
+    format: String,
+    version: u32,
+    linked: bool,
+    #[serde(default)]
+    time_signature: TimeSignature,
+    #[serde(default = "default_bars")]
+    bars: u32,
+    #[serde(default)]
+    range: ValueRange,
+    points: Vec<CurvePoint>,
+    #[serde(default)]
+    interpolations: Vec<Interpolation>,
+}
+
+/// Formats of all documents sharing [`FileError`], with what they contain and their latest version
+const DOCUMENTS: [(&str, &str, u32); 3] = [
+    (FORMAT, "curve", VERSION),
+    (clip::FORMAT, "clip", clip::VERSION),
+    (preset::FORMAT, "preset pack", preset::VERSION),
+];
+
+fn default_bars() -> u32 {
+    1
+}
+
+#[derive(Debug)]
+pub enum FileError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The document is valid JSON but of another format
+    Format(String),
+    /// The document was written by a newer version
+    Version(u32),
+    /// The loop has no beats or an unknown note value
+    Loop(TimeSignature, u32),
+    /// The output range is empty or not usable for logarithmic scaling
+    Range(ValueRange),
+    /// The points do not describe a valid curve
+    Structure {
+        index: usize,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(err) => write!(f, "Could not access file: {err}"),
+            FileError::Json(err) => write!(f, "Malformed document: {err}"),
+            // Every kind of document checks its own format, a known one is just the wrong kind
+            FileError::Format(format) => {
+                match DOCUMENTS.iter().find(|(known, ..)| known == format) {
+                    Some((_, kind, _)) => {
+                        write!(f, "Expected another kind of document, this is a {kind}")
+                    }
+                    None => {
+                        let expected: Vec<String> = DOCUMENTS
+                            .iter()
+                            .map(|(known, ..)| format!("{known:?}"))
+                            .collect();
+                        write!(
+                            f,
+                            "Unknown document format {format:?}, expected one of {}",
+                            expected.join(", ")
+                        )
+                    }
+                }
+            }
+            FileError::Version(version) => {
+                let latest: Vec<String> = DOCUMENTS
+                    .iter()
+                    .map(|(_, kind, latest)| format!("{latest} for {kind}s"))
+                    .collect();
+                write!(
+                    f,
+                    "Document version {version} is not supported, latest known versions are {}",
+                    latest.join(", ")
+                )
+            }
+            FileError::Loop(time_signature, bars) => {
+                write!(f, "Invalid loop of {bars} bars in {time_signature}")
+            }
+            FileError::Range(range) => write!(f, "Invalid value range {range}"),
+            FileError::Structure { index, reason } => {
+                write!(f, "Invalid curve at point {index}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Io(err) => Some(err),
+            FileError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FileError {
+    fn from(err: io::Error) -> Self {
+        FileError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for FileError {
+    fn from(err: serde_json::Error) -> Self {
+        FileError::Json(err)
+    }
+}
+
+impl From<Curve> for CurveFile {
+    fn from(curve: Curve) -> Self {
+        Self {
+            format: FORMAT.to_owned(),
+            version: VERSION,
+            linked: curve.linked,
+            time_signature: curve.time_signature,
+            bars: curve.bars,
+            range: curve.range,
+            points: curve.points,
+            interpolations: curve.interpolations,
+        }
+    }
+}
+
+impl TryFrom<CurveFile> for Curve {
+    type Error = FileError;
+
+    fn try_from(file: CurveFile) -> Result<Self, Self::Error> {
+        if file.format != FORMAT {
+            return Err(FileError::Format(file.format));
+        }
+        if file.version > VERSION {
+            return Err(FileError::Version(file.version));
+        }
+
+        let mut curve = Curve {
+            linked: file.linked,
+            time_signature: file.time_signature,
+            bars: file.bars,
+            range: file.range,
+            points: file.points,
+            interpolations: file.interpolations,
+        };
+        if curve.interpolations.is_empty() {
+            curve.interpolations = vec![Interpolation::Bezier; curve.segments().len()];
+        }
+        curve.validate()?;
+        Ok(curve)
+    }
+}
+
+impl Curve {
+    pub fn from_json(json: &str) -> Result<Self, FileError> {
+        serde_json::from_str::<CurveFile>(json)?.try_into()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&CurveFile::from(self.clone()))
+            .expect("Could not serialize curve")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FileError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FileError> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+
+    /// Checks the invariants `draw` and `value` rely on
+    pub(super) fn validate(&self) -> Result<(), FileError> {
+        let invalid = |index, reason| Err(FileError::Structure { index, reason });
+
+        if !self.time_signature.is_valid() || self.bars == 0 {
+            return Err(FileError::Loop(self.time_signature, self.bars));
+        }
+        if !self.range.is_valid() {
+            return Err(FileError::Range(self.range.clone()));
+        }
+        let length = self.length();
+
+        if self.points.len() < 3 {
+            return invalid(self.points.len(), "a curve needs at least two anchors");
+        }
+        let last = self.points.len() - 1;
+        let mut bezier_points = 0;
+
+        for (i, point) in self.points.iter().enumerate() {
+            let pos = point.pos();
+            if !pos.x.is_finite() || !pos.y.is_finite() {
+                return invalid(i, "position is not a finite number");
+            }
+            if !(0.0..=length).contains(&pos.x) || !(0.0..=100.0).contains(&pos.y) {
+                return invalid(i, "position is outside of the loop or 0.0..=100.0");
+            }
+
+            match point {
+                CurvePoint::First(_) if i != 0 => return invalid(i, "first point in the middle"),
+                CurvePoint::Last(_) if i != last => return invalid(i, "last point in the middle"),
+                _ if i == 0 && !matches!(point, CurvePoint::First(_)) => {
+                    return invalid(i, "the curve must start with a first point")
+                }
+                _ if i == last && !matches!(point, CurvePoint::Last(_)) => {
+                    return invalid(i, "the curve must end with a last point")
+                }
+                CurvePoint::Bezier(_) => {
+                    bezier_points += 1;
+                    if bezier_points > 2 {
+                        return invalid(i, "more than two bezier points between anchors");
+                    }
+                }
+                _ if i > 0 && bezier_points == 0 => {
+                    return invalid(i, "anchors must be separated by a bezier point")
+                }
+                _ => bezier_points = 0,
+            }
+
+            if i > 0 && pos.x < self.points[i - 1].pos().x {
+                return invalid(i, "points are not ordered by x");
+            }
+        }
+
+        if self.interpolations.len() != self.segments().len() {
+            return invalid(last, "there must be one interpolation for every segment");
+        }
+
+        if self.points[0].pos().x != 0.0 || self.points[last].pos().x != length {
+            return invalid(0, "the curve must span the whole loop");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Pos2;
+    use serde_json::{json, Value};
+
+    /// Position and reason of a structural error found by `validate`
+    fn structure_error(curve: &Curve) -> (usize, &'static str) {
+        match curve.validate() {
+            Err(FileError::Structure { index, reason }) => (index, reason),
+            other => panic!("Expected a structural error, got {other:?}"),
+        }
+    }
+
+    /// The document of `curve` with `field` replaced
+    fn document_with(curve: &Curve, field: &str, value: Value) -> String {
+        let mut document: Value = serde_json::from_str(&curve.to_json()).unwrap();
+        document[field] = value;
+        document.to_string()
+    }
+
+    #[test]
+    fn saved_curves_load_unchanged() {
+        let mut curve = Curve::forward();
+        curve.set_time_signature(TimeSignature::default(), 2);
+        curve.set_range(ValueRange::new(20.0, 20_000.0, "Hz").logarithmic());
+        curve.interpolations[1] = Interpolation::Step;
+
+        let path = std::env::temp_dir().join(format!("curve_file_{}.json", std::process::id()));
+        curve.save(&path).unwrap();
+        let loaded = Curve::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), curve);
+
+        assert_eq!(Curve::from_json(&curve.to_json()).unwrap(), curve);
+        assert!(matches!(
+            Curve::load(std::env::temp_dir().join("no_such_curve.json")),
+            Err(FileError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn other_formats_and_newer_versions_are_rejected() {
+        let curve = Curve::alternating();
+        let clip = document_with(&curve, "format", json!("ui_experiments/clip"));
+        assert!(matches!(
+            Curve::from_json(&clip),
+            Err(FileError::Format(format)) if format == "ui_experiments/clip"
+        ));
+        assert_eq!(
+            Curve::from_json(&clip).unwrap_err().to_string(),
+            "Expected another kind of document, this is a clip"
+        );
+        let other = document_with(&curve, "format", json!("other"));
+        assert_eq!(
+            Curve::from_json(&other).unwrap_err().to_string(),
+            "Unknown document format \"other\", expected one of \"ui_experiments/curve\", \"ui_experiments/clip\", \"ui_experiments/presets\""
+        );
+
+        let newer = document_with(&curve, "version", json!(VERSION + 1));
+        assert!(matches!(
+            Curve::from_json(&newer),
+            Err(FileError::Version(version)) if version == VERSION + 1
+        ));
+        assert_eq!(
+            FileError::Version(6).to_string(),
+            "Document version 6 is not supported, latest known versions are 5 for curves, 1 for clips, 1 for preset packs"
+        );
+
+        assert!(matches!(
+            Curve::from_json("{\"format\": 5}"),
+            Err(FileError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn older_versions_are_migrated() {
+        // Before version 5 all segments were beziers
+        let mut document: Value = serde_json::from_str(&Curve::alternating().to_json()).unwrap();
+        document["version"] = json!(4);
+        document.as_object_mut().unwrap().remove("interpolations");
+        let curve = Curve::from_json(&document.to_string()).unwrap();
+        assert_eq!(curve.interpolations, vec![Interpolation::Bezier; 4]);
+    }
+
+    #[test]
+    fn invalid_structures_are_rejected() {
+        let original = Curve::alternating();
+        let last = original.points.len() - 1;
+        let with = |index: usize, point: CurvePoint| {
+            let mut curve = original.clone();
+            curve.points[index] = point;
+            curve
+        };
+
+        assert_eq!(
+            structure_error(&with(0, CurvePoint::Bezier(Pos2::new(0.0, 100.0)))),
+            (0, "the curve must start with a first point")
+        );
+        assert_eq!(
+            structure_error(&with(last, CurvePoint::Bezier(Pos2::new(4.0, 100.0)))),
+            (last, "the curve must end with a last point")
+        );
+        assert_eq!(
+            structure_error(&with(2, CurvePoint::First(Pos2::new(1.0, 0.0)))),
+            (2, "first point in the middle")
+        );
+        assert_eq!(
+            structure_error(&with(2, CurvePoint::Last(Pos2::new(1.0, 0.0)))),
+            (2, "last point in the middle")
+        );
+        assert_eq!(
+            structure_error(&with(1, CurvePoint::Inner(Pos2::new(0.5, 50.0)))),
+            (1, "anchors must be separated by a bezier point")
+        );
+        assert_eq!(
+            structure_error(&with(3, CurvePoint::Bezier(Pos2::new(0.5, 50.0)))),
+            (3, "points are not ordered by x")
+        );
+        assert_eq!(
+            structure_error(&with(4, CurvePoint::Inner(Pos2::new(f32::NAN, 100.0)))),
+            (4, "position is not a finite number")
+        );
+        assert_eq!(
+            structure_error(&with(4, CurvePoint::Inner(Pos2::new(2.0, f32::INFINITY)))),
+            (4, "position is not a finite number")
+        );
+
+        let mut curve = original.clone();
+        curve
+            .points
+            .splice(1..1, [CurvePoint::Bezier(Pos2::new(0.1, 100.0)); 2]);
+        assert_eq!(
+            structure_error(&curve),
+            (3, "more than two bezier points between anchors")
+        );
+
+        let mut curve = original.clone();
+        curve.interpolations.pop();
+        assert_eq!(
+            structure_error(&curve),
+            (last, "there must be one interpolation for every segment")
+        );
+
+        // Loading checks the structure as well
+        let document = document_with(
+            &original,
+            "points",
+            json!([{"kind": "first", "x": 0.0, "y": 0.0}]),
+        );
+        assert!(matches!(
+            Curve::from_json(&document),
+            Err(FileError::Structure { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn curves_must_span_exactly_the_loop() {
+        let mut curve = Curve::alternating();
+        curve.set_time_signature(
+            TimeSignature {
+                beats: 3,
+                note_value: 4,
+            },
+            1,
+        );
+        let last = curve.points.len() - 1;
+
+        // Rounding errors at the loop end are not tolerated in either direction
+        curve.points[last].set_x(3.0000002);
+        assert_eq!(
+            structure_error(&curve),
+            (last, "position is outside of the loop or 0.0..=100.0")
+        );
+        curve.points[last].set_x(2.9999998);
+        assert_eq!(
+            structure_error(&curve),
+            (0, "the curve must span the whole loop")
+        );
+        curve.points[last].set_x(3.0);
+        assert!(curve.validate().is_ok());
+
+        assert!(matches!(
+            Curve::from_json(&document_with(&curve, "bars", json!(0))),
+            Err(FileError::Loop(_, 0))
+        ));
+    }
+}
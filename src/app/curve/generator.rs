@@ -0,0 +1,423 @@
+use super::{point::CurvePoint, Curve, Interpolation};
+use egui::Pos2;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, fmt};
+
+/// Lengths of the bezier handles of a quarter sine wave, at the zero crossing and at the peak,
+/// as fractions of the quarter. Deviates less than 0.01% of the amplitude from a sine.
+const SINE_HANDLES: (f32, f32) = (0.326, 0.362);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Waveform {
+    #[default]
+    Sine,
+    Triangle,
+    SawUp,
+    SawDown,
+    /// High for the duty cycle, then low
+    Square,
+    ExponentialUp,
+    ExponentialDown,
+    /// Holds a random value for every cycle
+    Random,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 8] = [
+        Waveform::Sine,
+        Waveform::Triangle,
+        Waveform::SawUp,
+        Waveform::SawDown,
+        Waveform::Square,
+        Waveform::ExponentialUp,
+        Waveform::ExponentialDown,
+        Waveform::Random,
+    ];
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Waveform::Sine => "Sine",
+            Waveform::Triangle => "Triangle",
+            Waveform::SawUp => "Saw up",
+            Waveform::SawDown => "Saw down",
+            Waveform::Square => "Square",
+            Waveform::ExponentialUp => "Exponential ramp up",
+            Waveform::ExponentialDown => "Exponential ramp down",
+            Waveform::Random => "Random (sample and hold)",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Settings for curves made of repeated cycles of a waveform
+///
+/// Values are fractions of the range, like the normalized values of `ValueRange`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Generator {
+    pub waveform: Waveform,
+    /// Cycles per loop
+    pub cycles: u32,
+    /// Fraction of a cycle the waveform starts at
+    pub phase: f32,
+    /// Distance between the lowest and the highest value
+    pub amplitude: f32,
+    /// Value in the middle between the lowest and the highest value
+    pub offset: f32,
+    /// Fraction of a cycle a square wave is high
+    pub duty: f32,
+    /// Random waveforms with the same seed have the same values
+    pub seed: u64,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::default(),
+            cycles: 1,
+            phase: 0.0,
+            amplitude: 1.0,
+            offset: 0.5,
+            duty: 0.5,
+            seed: 1,
+        }
+    }
+}
+
+/// End of a segment in cycle coordinates, `u` in `0.0..=1.0` through the cycle and `w` in
+/// `0.0..=1.0` from the lowest to the highest value
+struct Segment {
+    end: (f32, f32),
+    interpolation: Interpolation,
+    /// Bezier points, the middle between the anchors if there are none
+    controls: Vec<(f32, f32)>,
+}
+
+impl Segment {
+    fn new(end: (f32, f32), interpolation: Interpolation) -> Self {
+        Self {
+            end,
+            interpolation,
+            controls: Vec::new(),
+        }
+    }
+}
+
+impl Generator {
+    /// A curve with the loop length and range of `like`
+    ///
+    /// The phase splits the segment crossing the loop end, which keeps the shape of all waveforms
+    /// but the exponential ones.
+    pub fn curve(&self, like: &Curve) -> Curve {
+        let cycles = self.cycles.max(1);
+        let length = like.length();
+        let period = length / cycles as f32;
+        let to_pos = |cycle: u32, (u, w): (f32, f32)| {
+            let value = self.offset + self.amplitude * (w - 0.5);
+            // Multiplying by the period would miss the loop end by a rounding error
+            let x = length * (cycle as f32 + u) / cycles as f32;
+            Pos2::new(x.min(length), 100.0 * (1.0 - value.clamp(0.0, 1.0)))
+        };
+
+        let mut random = SplitMix64(self.seed);
+        let first_random = random.next_f32();
+        let mut start = to_pos(0, self.start());
+        let mut points = vec![CurvePoint::First(start)];
+        let mut interpolations = Vec::new();
+        for cycle in 0..cycles {
+            let last_cycle = cycle == cycles - 1;
+            let next_random = if last_cycle {
+                first_random
+            } else {
+                random.next_f32()
+            };
+            for segment in self.cycle(last_cycle, next_random) {
+                let end = to_pos(cycle, segment.end);
+                if segment.controls.is_empty() {
+                    points.push(CurvePoint::Bezier(start.lerp(end, 0.5)));
+                } else {
+                    points.extend(
+                        segment
+                            .controls
+                            .iter()
+                            .map(|control| CurvePoint::Bezier(to_pos(cycle, *control))),
+                    );
+                }
+                points.push(CurvePoint::Inner(end));
+                interpolations.push(segment.interpolation);
+                start = end;
+            }
+        }
+        let last = points.len() - 1;
+        points[last] = CurvePoint::Last(Pos2::new(length, start.y));
+
+        let mut curve = Curve {
+            linked: points[0].pos().y == start.y,
+            time_signature: like.time_signature,
+            bars: like.bars,
+            range: like.range.clone(),
+            points,
+            interpolations,
+        };
+        // Starting later in the cycle moves the waveform earlier in time
+        if let Err(err) = curve.shift(-self.phase * period) {
+            log::warn!("Could not apply the phase {}: {err}", self.phase);
+        }
+        curve
+    }
+
+    /// Value at the start of every cycle
+    fn start(&self) -> (f32, f32) {
+        match self.waveform {
+            Waveform::Sine | Waveform::Triangle => (0.0, 0.5),
+            Waveform::SawUp | Waveform::ExponentialUp => (0.0, 0.0),
+            Waveform::SawDown | Waveform::ExponentialDown | Waveform::Square => (0.0, 1.0),
+            // Set to the first random value by `curve`
+            Waveform::Random => (0.0, SplitMix64(self.seed).next_f32()),
+        }
+    }
+
+    /// Segments of one cycle starting at `start`
+    ///
+    /// Waveforms jumping back to their start do that at the beginning of the next cycle, the last
+    /// cycle ends before the jump. Random waveforms hold `next_random` at the end.
+    fn cycle(&self, last_cycle: bool, next_random: f32) -> Vec<Segment> {
+        use Interpolation::{Exponential, Hold, Linear};
+
+        let ramp = |from: f32, to: f32, interpolation| {
+            let mut segments = vec![Segment::new((1.0, to), interpolation)];
+            if !last_cycle {
+                segments.push(Segment::new((1.0, from), Linear));
+            }
+            segments
+        };
+        // Quarter sine waves as cubic beziers, from the middle to the peaks or back
+        let (crossing, peak) = SINE_HANDLES;
+        let slope = 0.5 * PI / 2.0;
+        let quarter = |from: (f32, f32), to: (f32, f32), from_middle: bool| {
+            let (du, dw) = (to.0 - from.0, to.1 - from.1);
+            let controls = if from_middle {
+                vec![
+                    (
+                        from.0 + crossing * du,
+                        from.1 + crossing * slope * dw.signum(),
+                    ),
+                    (to.0 - peak * du, to.1),
+                ]
+            } else {
+                vec![
+                    (from.0 + peak * du, from.1),
+                    (to.0 - crossing * du, to.1 - crossing * slope * dw.signum()),
+                ]
+            };
+            Segment {
+                end: to,
+                interpolation: Interpolation::Bezier,
+                controls,
+            }
+        };
+
+        match self.waveform {
+            Waveform::Sine => vec![
+                quarter((0.0, 0.5), (0.25, 1.0), true),
+                quarter((0.25, 1.0), (0.5, 0.5), false),
+                quarter((0.5, 0.5), (0.75, 0.0), true),
+                quarter((0.75, 0.0), (1.0, 0.5), false),
+            ],
+            Waveform::Triangle => vec![
+                Segment::new((0.25, 1.0), Linear),
+                Segment::new((0.75, 0.0), Linear),
+                Segment::new((1.0, 0.5), Linear),
+            ],
+            Waveform::SawUp => ramp(0.0, 1.0, Linear),
+            Waveform::SawDown => ramp(1.0, 0.0, Linear),
+            Waveform::ExponentialUp => ramp(0.0, 1.0, Exponential),
+            Waveform::ExponentialDown => ramp(1.0, 0.0, Exponential),
+            Waveform::Square => {
+                let duty = self.duty.clamp(0.0, 1.0);
+                vec![
+                    Segment::new((duty, 0.0), Hold),
+                    Segment::new((1.0, 1.0), Hold),
+                ]
+            }
+            Waveform::Random => vec![Segment::new((1.0, next_random), Hold)],
+        }
+    }
+}
+
+/// Small random number generator, the same seed always gives the same numbers
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_f32(&mut self) -> f32 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // The upper 24 bits fit into the mantissa of a f32
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::TimeSignature;
+
+    /// Allowed difference to the expected values, in the unit of the default range `0.0..=100.0`
+    const TOLERANCE: f32 = 1e-2;
+
+    fn generator(waveform: Waveform, cycles: u32) -> Generator {
+        Generator {
+            waveform,
+            cycles,
+            ..Default::default()
+        }
+    }
+
+    /// Curve to take the loop and range from
+    fn like(beats: u32, note_value: u32, bars: u32) -> Curve {
+        let mut curve = Curve::fixed();
+        curve.set_time_signature(TimeSignature { beats, note_value }, bars);
+        curve
+    }
+
+    /// Compares the curve to `expected`, a function of the fraction of the loop, at sampled beats
+    fn assert_matches(curve: &Curve, expected: impl Fn(f32) -> f32) {
+        let length = curve.length();
+        for i in 0..=4_000 {
+            let x = length * i as f32 / 4_000.0;
+            let (value, expected) = (curve.value(x), expected(x / length));
+            assert!(
+                (value - expected).abs() < TOLERANCE,
+                "x = {x}: {value} instead of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn generated_curves_are_valid() {
+        for (beats, note_value, bars) in [(4, 4, 1), (3, 4, 1), (4, 4, 4), (5, 4, 2), (7, 8, 3)] {
+            let like = like(beats, note_value, bars);
+            let loop_name = format!("{bars} bars of {beats}/{note_value}");
+            for waveform in Waveform::ALL {
+                for cycles in 1..=64 {
+                    for phase in [0.0, 0.3] {
+                        let generator = Generator {
+                            phase,
+                            ..generator(waveform, cycles)
+                        };
+                        let curve = generator.curve(&like);
+                        let name = format!("{cycles} cycles of {waveform} with phase {phase}");
+                        if let Err(err) = curve.validate() {
+                            panic!("{name} in {loop_name}: {err}");
+                        }
+                        // Lanes store their curves the same way
+                        let stored = serde_json::to_string(&curve).unwrap();
+                        assert_eq!(
+                            serde_json::from_str::<Curve>(&stored).unwrap(),
+                            curve,
+                            "{name} in {loop_name}"
+                        );
+                        assert_eq!(
+                            Curve::from_json(&curve.to_json()).unwrap(),
+                            curve,
+                            "{name} in {loop_name}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sine_follows_sin() {
+        for cycles in [1, 3, 16] {
+            let curve = generator(Waveform::Sine, cycles).curve(&Curve::fixed());
+            assert_matches(&curve, |x| {
+                50.0 + 50.0 * (2.0 * PI * cycles as f32 * x).sin()
+            });
+        }
+    }
+
+    #[test]
+    fn phase_shifts_the_waveform() {
+        let shifted = Generator {
+            phase: 0.25,
+            ..generator(Waveform::Sine, 2)
+        }
+        .curve(&Curve::fixed());
+        assert_matches(&shifted, |x| {
+            50.0 + 50.0 * (2.0 * PI * (2.0 * x + 0.25)).sin()
+        });
+
+        let original = generator(Waveform::Triangle, 2).curve(&Curve::fixed());
+        let shifted = Generator {
+            phase: 0.5,
+            ..generator(Waveform::Triangle, 2)
+        }
+        .curve(&Curve::fixed());
+        // Half a cycle of two in four beats is one beat
+        assert_matches(&shifted, |x| original.value(4.0 * x + 1.0));
+    }
+
+    #[test]
+    fn square_is_high_for_the_duty_cycle() {
+        let curve = Generator {
+            duty: 0.25,
+            ..generator(Waveform::Square, 2)
+        }
+        .curve(&Curve::fixed());
+        // Two cycles of two beats, each high for half a beat
+        assert_matches(
+            &curve,
+            |x| {
+                if (2.0 * x).fract() < 0.25 {
+                    100.0
+                } else {
+                    0.0
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn random_values_depend_on_the_seed() {
+        let random = |seed| {
+            Generator {
+                seed,
+                ..generator(Waveform::Random, 8)
+            }
+            .curve(&Curve::fixed())
+        };
+        let curve = random(7);
+        assert_eq!(curve, random(7));
+        assert_ne!(curve, random(8));
+
+        // Every cycle holds its value, the loop ends where it started
+        for cycle in 0..8 {
+            let start = curve.value(cycle as f32 * 0.5);
+            assert_eq!(curve.value(cycle as f32 * 0.5 + 0.49), start);
+        }
+        assert!(curve.linked());
+        assert_eq!(curve.value(0.0), curve.value(4.0));
+    }
+
+    #[test]
+    fn waveforms_returning_to_their_start_are_linked() {
+        for waveform in Waveform::ALL {
+            let curve = generator(waveform, 3).curve(&Curve::fixed());
+            let returns = matches!(
+                waveform,
+                Waveform::Sine | Waveform::Triangle | Waveform::Square | Waveform::Random
+            );
+            assert_eq!(curve.linked(), returns, "{waveform}");
+            let (first, last) = (curve.points[0], curve.points[curve.points.len() - 1]);
+            assert_eq!(first.pos().y == last.pos().y, returns, "{waveform}");
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use super::{Curve, Edit};
+use egui::{DragValue, Pos2, Rect, Ui};
+
+impl Curve {
+    /// Where point `index` can be moved without passing its neighbors, in curve coordinates
+    ///
+    /// The first and last point stay at the ends of the loop.
+    pub fn point_limits(&self, index: usize) -> Option<Rect> {
+        let point = self.points.get(index)?;
+        let (min_x, max_x) = if point.is_outer() {
+            (point.pos().x, point.pos().x)
+        } else {
+            (
+                self.points[index - 1].pos().x,
+                self.points[index + 1].pos().x,
+            )
+        };
+        Some(Rect::from_min_max(
+            Pos2::new(min_x, 0.0),
+            Pos2::new(max_x, 100.0),
+        ))
+    }
+
+    /// Beat and value at `pos` in curve coordinates
+    pub fn format_position(&self, pos: Pos2) -> String {
+        format!(
+            "Beat {}\n{}",
+            format_beat(pos.x),
+            self.range.format(self.y_to_value(pos.y))
+        )
+    }
+
+    /// Kind and exact coordinates of point `index`, editing them returns a move
+    pub fn inspector_ui(&self, ui: &mut Ui, index: usize) -> Option<Edit> {
+        let point = self.points.get(index)?;
+        let limits = self.point_limits(index)?;
+        let pos = point.pos();
+        let old_value = self.y_to_value(pos.y);
+        let (mut beat, mut value) = (pos.x, old_value);
+        let (low, high) = (
+            self.range.min.min(self.range.max),
+            self.range.min.max(self.range.max),
+        );
+        // Logarithmic ranges change by the same ratio for every pixel dragged
+        let speed = if self.range.logarithmic {
+            old_value.abs() * 0.01
+        } else {
+            (high - low) * 0.005
+        };
+        let unit = if self.range.unit.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", self.range.unit)
+        };
+
+        egui::Grid::new("inspector").num_columns(2).show(ui, |ui| {
+            ui.label("Kind");
+            ui.label(point.name());
+            ui.end_row();
+
+            ui.label("Beat");
+            ui.add_enabled(
+                limits.min.x < limits.max.x,
+                DragValue::new(&mut beat)
+                    .clamp_range(limits.min.x..=limits.max.x)
+                    .speed(0.01)
+                    .max_decimals(3),
+            )
+            .on_hover_text(format!(
+                "Between beat {} and {}, the neighbors of the point",
+                format_beat(limits.min.x),
+                format_beat(limits.max.x)
+            ))
+            .on_disabled_hover_text("The first and last point stay at the ends of the loop");
+            ui.end_row();
+
+            ui.label("Value");
+            ui.add(
+                DragValue::new(&mut value)
+                    .clamp_range(low..=high)
+                    .speed(speed)
+                    .suffix(unit),
+            )
+            .on_hover_text(format!("Inside the range {}", self.range));
+            ui.end_row();
+        });
+
+        // Converting back and forth would move points which were not edited by rounding errors
+        if beat == pos.x && value == old_value {
+            return None;
+        }
+        let y = if value == old_value {
+            pos.y
+        } else {
+            self.value_to_y(value)
+        };
+        Some(Edit::MovePoint {
+            index,
+            pos: Pos2::new(beat, y),
+        })
+    }
+}
+
+fn format_beat(beat: f32) -> String {
+    let text = format!("{beat:.3}");
+    text.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
@@ -0,0 +1,88 @@
+use egui::Pos2;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, fmt};
+
+/// How a segment gets from the value of its start anchor to the value of its end anchor
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Quadratic or cubic bezier shaped by the bezier points of the segment
+    #[default]
+    Bezier,
+    Linear,
+    /// Jumps to the end value right at the start of the segment
+    Step,
+    /// Keeps the start value until the end of the segment
+    Hold,
+    Smoothstep,
+    /// Half a cosine wave, eases in and out
+    Sine,
+    /// Exponential ease in
+    Exponential,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 7] = [
+        Interpolation::Bezier,
+        Interpolation::Linear,
+        Interpolation::Step,
+        Interpolation::Hold,
+        Interpolation::Smoothstep,
+        Interpolation::Sine,
+        Interpolation::Exponential,
+    ];
+
+    /// Fraction of the way from start to end value at `t` in `0.0..=1.0` of the segment
+    ///
+    /// Not used for `Bezier`, its shape depends on the bezier points.
+    pub fn ease(&self, t: f32) -> f32 {
+        match self {
+            Interpolation::Bezier | Interpolation::Linear => t,
+            Interpolation::Step => 1.0,
+            Interpolation::Hold => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Interpolation::Smoothstep => t * t * (3.0 - 2.0 * t),
+            Interpolation::Sine => (1.0 - (PI * t).cos()) / 2.0,
+            Interpolation::Exponential => (2.0f32.powf(10.0 * t) - 1.0) / 1023.0,
+        }
+    }
+
+    /// Polyline from `from` to `to` for drawing, not used for `Bezier`
+    pub fn path(&self, from: Pos2, to: Pos2) -> Vec<Pos2> {
+        const SAMPLES: usize = 48;
+
+        match self {
+            Interpolation::Step => vec![from, Pos2::new(from.x, to.y), to],
+            Interpolation::Hold => vec![from, Pos2::new(to.x, from.y), to],
+            _ => (0..=SAMPLES)
+                .map(|i| {
+                    let t = i as f32 / SAMPLES as f32;
+                    Pos2::new(
+                        emath::lerp(from.x..=to.x, t),
+                        emath::lerp(from.y..=to.y, self.ease(t)),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Interpolation::Bezier => "Bezier",
+            Interpolation::Linear => "Linear",
+            Interpolation::Step => "Step",
+            Interpolation::Hold => "Hold",
+            Interpolation::Smoothstep => "Smoothstep",
+            Interpolation::Sine => "Sine",
+            Interpolation::Exponential => "Exponential",
+        };
+        write!(f, "{name}")
+    }
+}
@@ -0,0 +1,129 @@
+use super::{Curve, Edit, OutOfRange, Snap};
+use egui::{Context, Key, Modifiers, Vec2};
+use std::collections::BTreeSet;
+
+/// Keys handled by `Curve::keyboard` and what they do, for the overview of all shortcuts
+pub const SHORTCUTS: [(&str, &str); 8] = [
+    ("Tab / Shift+Tab", "Select the next / previous point"),
+    ("Ctrl+→ / Ctrl+←", "Select the next / previous point"),
+    (
+        "Arrows",
+        "Move the selection by a grid step or 1% of the range",
+    ),
+    ("Shift+Arrows", "Move by a beat or 10% of the range"),
+    (
+        "Alt+Arrows",
+        "Move by a tenth of a grid step or 0.1% of the range",
+    ),
+    ("Delete", "Remove the selected anchors"),
+    ("Insert", "Add an anchor at the playhead"),
+    ("Escape", "Clear the selection"),
+];
+
+impl Curve {
+    /// Selects, moves, removes and inserts points with the keyboard like `draw` does with the mouse
+    ///
+    /// Arrows move by the grid steps of `snap`, new anchors are inserted at the beat `playhead`.
+    pub fn keyboard(
+        &mut self,
+        ctx: &Context,
+        snap: &Snap,
+        selection: &mut BTreeSet<usize>,
+        playhead: f32,
+    ) {
+        let hidden = self.hidden_points();
+        let shown: Vec<usize> = (0..self.points.len()).filter(|i| !hidden[*i]).collect();
+        selection.retain(|i| shown.contains(i));
+
+        let (modifiers, cycle, nudge, delete, insert, escape) = ctx.input_mut(|input| {
+            // Extra shift and alt are ignored, so the more specific keys come first
+            let mut count = |modifiers, key| input.count_and_consume_key(modifiers, key) as i32;
+            let cycle = count(Modifiers::COMMAND, Key::ArrowRight)
+                - count(Modifiers::COMMAND, Key::ArrowLeft)
+                - count(Modifiers::SHIFT, Key::Tab)
+                + count(Modifiers::NONE, Key::Tab);
+            let nudge = Vec2::new(
+                (count(Modifiers::NONE, Key::ArrowRight) - count(Modifiers::NONE, Key::ArrowLeft))
+                    as f32,
+                (count(Modifiers::NONE, Key::ArrowDown) - count(Modifiers::NONE, Key::ArrowUp))
+                    as f32,
+            );
+            let delete =
+                count(Modifiers::NONE, Key::Delete) + count(Modifiers::NONE, Key::Backspace);
+            let insert = count(Modifiers::NONE, Key::Insert);
+            let escape = count(Modifiers::NONE, Key::Escape);
+            (
+                input.modifiers,
+                cycle,
+                nudge,
+                delete > 0,
+                insert > 0,
+                escape > 0,
+            )
+        });
+
+        if escape {
+            selection.clear();
+        }
+
+        if cycle != 0 && !shown.is_empty() {
+            let count = shown.len() as i32;
+            let current = selection
+                .last()
+                .and_then(|i| shown.iter().position(|shown| shown == i));
+            let next = match current {
+                Some(current) => current as i32 + cycle,
+                // The first step selects the first or last point
+                None if cycle > 0 => cycle - 1,
+                None => count + cycle,
+            };
+            selection.clear();
+            selection.insert(shown[next.rem_euclid(count) as usize]);
+        }
+
+        let mut edits = Vec::new();
+        if nudge != Vec2::ZERO && !selection.is_empty() {
+            let grid = 1.0 / snap.subdivision.max(1) as f32;
+            let step = if modifiers.shift {
+                Vec2::new(1.0, 10.0)
+            } else if modifiers.alt {
+                Vec2::new(grid / 10.0, 0.1)
+            } else {
+                Vec2::new(grid, 1.0)
+            };
+            edits.push(Edit::MovePoints {
+                indices: selection.iter().copied().collect(),
+                delta: nudge * step,
+            });
+        }
+        if delete {
+            // From the back, removing an anchor shifts the indices of all points after it
+            edits.extend(
+                selection
+                    .iter()
+                    .rev()
+                    .filter(|i| self.points[**i].is_inner())
+                    .map(|i| Edit::RemoveAnchor { index: *i }),
+            );
+            selection.clear();
+        }
+        for edit in edits {
+            if let Err(err) = self.apply(&edit) {
+                log::warn!("Could not apply {edit:?}: {err}");
+            }
+        }
+
+        if insert {
+            let x = self
+                .position_in_loop(playhead, OutOfRange::Wrap)
+                .unwrap_or_default();
+            match self.insert_anchor(x, None) {
+                Ok(index) => {
+                    selection.clear();
+                    selection.insert(index);
+                }
+                Err(err) => log::warn!("Could not insert an anchor at beat {x}: {err}"),
+            }
+        }
+    }
+}
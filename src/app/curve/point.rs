@@ -1,21 +1,30 @@
 use egui::{Color32, Pos2, Rect, Rounding, Shape, Stroke, Vec2};
 use emath::RectTransform;
+use serde::{Deserialize, Serialize};
 
 const CONTROL_POINT_RADIUS: f32 = 8.0;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum CurvePoint {
     First(Pos2),
     /// All other points
     Inner(Pos2),
-    /// In between each Outer and Inner Points are the Bezier points to define the curves
+    /// Inner point which keeps the bezier points around it on one line
+    Smooth(Pos2),
+    /// In between each Outer and Inner Points are one (quadratic) or two (cubic) Bezier points to
+    /// define the curves
     Bezier(Pos2),
     Last(Pos2),
 }
 
 impl CurvePoint {
     pub fn is_inner(&self) -> bool {
-        matches!(self, CurvePoint::Inner(_))
+        matches!(self, CurvePoint::Inner(_) | CurvePoint::Smooth(_))
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        matches!(self, CurvePoint::Smooth(_))
     }
 
     pub fn is_outer(&self) -> bool {
@@ -26,6 +35,21 @@ impl CurvePoint {
         matches!(self, CurvePoint::Bezier(_))
     }
 
+    pub fn is_anchor(&self) -> bool {
+        !self.is_bezier()
+    }
+
+    /// Kind of the point as shown to the user
+    pub fn name(&self) -> &'static str {
+        match self {
+            CurvePoint::First(_) => "First point",
+            CurvePoint::Inner(_) => "Anchor",
+            CurvePoint::Smooth(_) => "Smooth anchor",
+            CurvePoint::Bezier(_) => "Bezier point",
+            CurvePoint::Last(_) => "Last point",
+        }
+    }
+
     pub fn point_rect(&self, to_screen: RectTransform) -> Rect {
         Rect::from_center_size(
             self.screen_pos(to_screen),
@@ -50,6 +74,19 @@ impl CurvePoint {
             CurvePoint::Inner(_) => {
                 Shape::rect_stroke(self.point_rect(to_screen), Rounding::default(), stroke)
             }
+            CurvePoint::Smooth(_) => {
+                let point_rect = self.point_rect(to_screen);
+                Shape::convex_polygon(
+                    vec![
+                        point_rect.center_top(),
+                        point_rect.right_center(),
+                        point_rect.center_bottom(),
+                        point_rect.left_center(),
+                    ],
+                    Color32::TRANSPARENT,
+                    stroke,
+                )
+            }
             CurvePoint::Bezier(_) => {
                 Shape::circle_stroke(self.screen_pos(to_screen), CONTROL_POINT_RADIUS, stroke)
             }
@@ -72,23 +109,33 @@ impl CurvePoint {
         to_screen.transform_pos(self.pos())
     }
 
-    pub fn set_screen_pos(&mut self, to_screen: RectTransform, screen_pos: Pos2) {
-        self.set_pos(to_screen.inverse().transform_pos_clamped(screen_pos));
-    }
-
     pub fn pos(&self) -> Pos2 {
         match self {
             CurvePoint::First(pos) => *pos,
             CurvePoint::Inner(pos) => *pos,
+            CurvePoint::Smooth(pos) => *pos,
             CurvePoint::Bezier(pos) => *pos,
             CurvePoint::Last(pos) => *pos,
         }
     }
 
+    /// Moves the point in time, unlike `set_pos` this also applies to the outer points
+    pub fn set_x(&mut self, x: f32) {
+        match self {
+            CurvePoint::First(pos)
+            | CurvePoint::Inner(pos)
+            | CurvePoint::Smooth(pos)
+            | CurvePoint::Bezier(pos)
+            | CurvePoint::Last(pos) => pos.x = x,
+        }
+    }
+
     pub fn set_pos(&mut self, new_pos: Pos2) {
         match self {
             CurvePoint::First(pos) | CurvePoint::Last(pos) => pos.y = new_pos.y,
-            CurvePoint::Inner(pos) | CurvePoint::Bezier(pos) => *pos = new_pos,
+            CurvePoint::Inner(pos) | CurvePoint::Smooth(pos) | CurvePoint::Bezier(pos) => {
+                *pos = new_pos
+            }
         }
     }
 }
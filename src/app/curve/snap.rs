@@ -0,0 +1,82 @@
+use super::Curve;
+use egui::{Modifiers, Pos2};
+use serde::{Deserialize, Serialize};
+
+/// Grid steps per beat with their names, triplets divide a beat in three
+pub const SUBDIVISIONS: [(u32, &str); 8] = [
+    (1, "1"),
+    (2, "1/2"),
+    (4, "1/4"),
+    (8, "1/8"),
+    (16, "1/16"),
+    (3, "1/2 triplets"),
+    (6, "1/4 triplets"),
+    (12, "1/8 triplets"),
+];
+
+/// Where dragged and inserted points land, the grid of `Curve::draw` shows the same steps
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snap {
+    pub enabled: bool,
+    /// Grid steps per beat
+    pub subdivision: u32,
+    /// Values snap to multiples of this in the unit of the range, `None` leaves them free
+    pub value_step: Option<f32>,
+}
+
+impl Default for Snap {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            subdivision: 4,
+            value_step: None,
+        }
+    }
+}
+
+impl Snap {
+    /// Holding alt snaps while snapping is off and the other way around
+    pub fn is_active(&self, modifiers: Modifiers) -> bool {
+        self.enabled != modifiers.alt
+    }
+}
+
+impl Curve {
+    /// Nearest grid position to `pos` inside the loop, in curve coordinates
+    pub fn snap(&self, pos: Pos2, snap: &Snap) -> Pos2 {
+        let subdivision = snap.subdivision.max(1) as f32;
+        let x = ((pos.x * subdivision).round() / subdivision).clamp(0.0, self.length());
+        let y = match snap.value_step.filter(|step| *step > 0.0) {
+            Some(step) => {
+                let value = self.y_to_value(pos.y);
+                let snapped = self.value_to_y((value / step).round() * step);
+                // The nearest step may be outside of the range, e.g. for ranges not starting at 0
+                if snapped.is_finite() && (0.0..=100.0).contains(&snapped) {
+                    snapped
+                } else {
+                    pos.y.clamp(0.0, 100.0)
+                }
+            }
+            None => pos.y,
+        };
+        Pos2::new(x, y)
+    }
+
+    /// Multiples of `step` inside the range, `None` if there are more than `max`
+    pub(super) fn value_steps(&self, step: f32, max: usize) -> Option<Vec<f32>> {
+        let (low, high) = (
+            self.range.min.min(self.range.max),
+            self.range.min.max(self.range.max),
+        );
+        let (first, last) = ((low / step).ceil(), (high / step).floor());
+        if step <= 0.0 || !(last - first).is_finite() || last - first >= max as f32 {
+            return None;
+        }
+        Some(
+            (first as i64..=last as i64)
+                .map(|i| i as f32 * step)
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const NOTE_VALUES: [u32; 6] = [1, 2, 4, 8, 16, 32];
+
+/// Beats of a curve are counted in `note_value` notes, so a bar of 6/8 has six beats
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub beats: u32,
+    pub note_value: u32,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            beats: 4,
+            note_value: 4,
+        }
+    }
+}
+
+impl TimeSignature {
+    pub fn is_valid(&self) -> bool {
+        self.beats > 0 && NOTE_VALUES.contains(&self.note_value)
+    }
+}
+
+impl fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.beats, self.note_value)
+    }
+}
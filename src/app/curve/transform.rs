@@ -0,0 +1,309 @@
+use super::{point::CurvePoint, Curve, EditError, Interpolation};
+use egui::Pos2;
+
+/// Operations on the whole curve, the points keep their kinds and the segments their
+/// interpolations
+impl Curve {
+    /// Plays the curve backwards
+    pub fn reverse(&mut self) {
+        self.reverse_span(0, self.points.len() - 1);
+    }
+
+    /// Turns the curve upside down, the maximum of the range becomes the minimum
+    pub fn invert(&mut self) {
+        for point in &mut self.points {
+            let pos = point.pos();
+            point.set_pos(Pos2::new(pos.x, 100.0 - pos.y));
+        }
+    }
+
+    /// Multiplies the distance of every point to the minimum of the range by `factor`
+    ///
+    /// Points leaving the range are moved back to its maximum.
+    pub fn scale_values(&mut self, factor: f32) -> Result<(), EditError> {
+        if !factor.is_finite() || factor < 0.0 {
+            return Err(EditError::ValueFactor(factor));
+        }
+        for point in &mut self.points {
+            let pos = point.pos();
+            let y = 100.0 - (100.0 - pos.y) * factor;
+            point.set_pos(Pos2::new(pos.x, y.clamp(0.0, 100.0)));
+        }
+        Ok(())
+    }
+
+    /// Raises all points by `offset` times the range, negative offsets lower them
+    ///
+    /// Points leaving the range are moved back to its nearest end.
+    pub fn offset_values(&mut self, offset: f32) -> Result<(), EditError> {
+        if !offset.is_finite() {
+            return Err(EditError::NotFinite(offset));
+        }
+        for point in &mut self.points {
+            let pos = point.pos();
+            let y = pos.y - offset * 100.0;
+            point.set_pos(Pos2::new(pos.x, y.clamp(0.0, 100.0)));
+        }
+        Ok(())
+    }
+
+    /// Moves the curve `beats` later, the part pushed past the loop end wraps around to the start
+    ///
+    /// Where the old loop end meets the old start there is a vertical jump unless the curve is
+    /// linked.
+    pub fn shift(&mut self, beats: f32) -> Result<(), EditError> {
+        if !beats.is_finite() {
+            return Err(EditError::NotFinite(beats));
+        }
+        let length = self.length();
+        let shift = beats.rem_euclid(length);
+        if shift <= 0.0 || shift >= length {
+            return Ok(());
+        }
+
+        // The part after `split` moves to the start of the loop. Linked curves start and end at
+        // the same value, so a vertical jump at `split` stays inside the loop for them.
+        let split = length - shift;
+        let end = self.split_at(split, false);
+        let start = if self.linked {
+            end
+        } else {
+            self.split_at(split, true)
+        };
+        let last = self.points.len() - 1;
+        let (_, before) = self.segment_span(0, end);
+        let (after, _) = self.segment_span(start, last);
+        let moved = |point: &CurvePoint, delta: f32, min: f32, max: f32| {
+            let mut point = *point;
+            point.set_x((point.pos().x + delta).clamp(min, max));
+            point
+        };
+
+        let mut points = vec![CurvePoint::First(Pos2::new(
+            0.0,
+            self.points[start].pos().y,
+        ))];
+        points.extend(
+            self.points[start + 1..last]
+                .iter()
+                .map(|point| moved(point, -split, 0.0, shift)),
+        );
+        let mut interpolations = self.interpolations[after..].to_vec();
+        let old_end = Pos2::new(shift, self.points[last].pos().y);
+        let old_start = Pos2::new(shift, self.points[0].pos().y);
+        points.push(CurvePoint::Inner(old_end));
+        if old_end != old_start {
+            points.push(CurvePoint::Bezier(old_end.lerp(old_start, 0.5)));
+            points.push(CurvePoint::Inner(old_start));
+            interpolations.push(Interpolation::default());
+        }
+        points.extend(
+            self.points[1..end]
+                .iter()
+                .map(|point| moved(point, shift, shift, length)),
+        );
+        points.push(CurvePoint::Last(Pos2::new(
+            length,
+            self.points[end].pos().y,
+        )));
+        interpolations.extend_from_slice(&self.interpolations[..=before]);
+
+        self.points = points;
+        self.interpolations = interpolations;
+        Ok(())
+    }
+
+    /// Stretches the beats `from..to` by `factor`, the rest of the loop after them is squeezed
+    /// or stretched to keep the loop length
+    pub fn stretch_range(&mut self, from: f32, to: f32, factor: f32) -> Result<(), EditError> {
+        self.check_range(from, to)?;
+        let length = self.length();
+        let new_to = from + (to - from) * factor;
+        // There must be some room left after the range, unless it is not stretched at all
+        if !(factor > 0.0 && ((new_to < length && to < length) || new_to == to)) {
+            return Err(EditError::Stretch(factor));
+        }
+
+        self.split_range(from, to);
+        let warp = |x: f32| {
+            if x <= from {
+                x
+            } else if x <= to {
+                from + (x - from) * factor
+            } else {
+                new_to + (x - to) * (length - new_to) / (length - to)
+            }
+        };
+        for point in &mut self.points {
+            point.set_x(warp(point.pos().x).clamp(0.0, length));
+        }
+        // Avoid rounding errors at the loop end
+        if let Some(last) = self.points.last_mut() {
+            last.set_x(length);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `curve` has the values of `original` moved `beats` later, away from the jumps at
+    /// whole beats
+    fn assert_shifted(curve: &Curve, original: &Curve, beats: f32) {
+        assert!(curve.validate().is_ok(), "{curve:?}");
+        let length = original.length();
+        for i in 0..400 {
+            let x = i as f32 / 100.0 + 0.005;
+            let expected = original.value((x - beats).rem_euclid(length));
+            assert!(
+                (curve.value(x) - expected).abs() < 1e-3,
+                "x = {x}: {} instead of {expected}",
+                curve.value(x)
+            );
+        }
+    }
+
+    #[test]
+    fn reverse_plays_backwards() {
+        let mut curve = Curve::forward();
+        curve.reverse();
+        // Ramps down instead of up, like the backward curve did before there was a reverse
+        let backward = Curve::from_points(
+            false,
+            vec![
+                (0.0, 0.0),
+                (1.0, 100.0),
+                (1.0, 0.0),
+                (2.0, 100.0),
+                (2.0, 0.0),
+                (3.0, 100.0),
+                (3.0, 0.0),
+                (4.0, 100.0),
+            ],
+        );
+        assert_eq!(curve, backward);
+        assert_eq!(Curve::backward(), backward);
+
+        curve.reverse();
+        assert_eq!(curve, Curve::forward());
+    }
+
+    #[test]
+    fn scaling_and_offsetting_values() {
+        let mut curve = Curve::alternating();
+        curve.scale_values(0.5).unwrap();
+        assert_eq!(curve.value(0.0), 0.0);
+        assert_eq!(curve.value(1.0), 50.0);
+        // Values leaving the range stop at its ends
+        curve.scale_values(4.0).unwrap();
+        assert_eq!(curve.value(1.0), 100.0);
+        curve.offset_values(-0.25).unwrap();
+        assert_eq!(curve.value(0.0), 0.0);
+        assert_eq!(curve.value(1.0), 75.0);
+
+        let original = curve.clone();
+        for factor in [-1.0, f32::NAN, f32::INFINITY] {
+            let error = curve.scale_values(factor).unwrap_err();
+            assert_eq!(format!("{error:?}"), format!("ValueFactor({factor:?})"));
+        }
+        assert!(curve.offset_values(f32::NAN).is_err());
+        assert_eq!(curve, original);
+    }
+
+    #[test]
+    fn shifting_by_nothing_or_whole_loops() {
+        for curve in [Curve::forward(), Curve::alternating()] {
+            for beats in [0.0, 4.0, -4.0, 8.0, -1e-9] {
+                let mut shifted = curve.clone();
+                shifted.shift(beats).unwrap();
+                assert_eq!(shifted, curve, "{beats}");
+            }
+        }
+        assert!(Curve::forward().shift(f32::INFINITY).is_err());
+    }
+
+    #[test]
+    fn shifting_wraps_around() {
+        for original in [Curve::forward(), Curve::alternating()] {
+            for beats in [0.5, 1.25, -0.75, 3.9, 6.5] {
+                let mut curve = original.clone();
+                curve.shift(beats).unwrap();
+                assert_shifted(&curve, &original, beats);
+                assert_eq!(curve.linked, original.linked);
+            }
+        }
+
+        // Linked curves need no jump at the old loop end
+        let mut curve = Curve::alternating();
+        curve.shift(1.0).unwrap();
+        assert_eq!(curve.points.len(), Curve::alternating().points.len());
+    }
+
+    #[test]
+    fn shifting_over_a_vertical_jump() {
+        // The saw wave jumps back down at every beat, shifting by whole beats keeps it the same
+        let mut curve = Curve::forward();
+        curve.shift(1.0).unwrap();
+        assert_eq!(curve, Curve::forward());
+
+        // Jumps moved past the loop end come out at the start, the old ends of the loop meet at
+        // the shift with a new jump
+        let mut curve = Curve::forward();
+        curve.shift(1.5).unwrap();
+        assert_shifted(&curve, &Curve::forward(), 1.5);
+        let jumps: Vec<f32> = curve
+            .segments()
+            .into_iter()
+            .map(|(start, end)| (curve.points[start].pos(), curve.points[end].pos()))
+            .filter(|(start, end)| start.x == end.x)
+            .map(|(start, _)| start.x)
+            .collect();
+        assert_eq!(jumps, vec![0.5, 1.5, 2.5, 3.5]);
+    }
+
+    #[test]
+    fn stretching_a_range() {
+        let mut curve = Curve::alternating();
+        curve.stretch_range(0.0, 1.0, 2.0).unwrap();
+        assert!(curve.validate().is_ok());
+        assert_eq!(curve.position(2), Some(Pos2::new(2.0, 0.0)));
+        // The rest of the loop is squeezed into the remaining two beats
+        assert_eq!(curve.position(4), Some(Pos2::new(2.0 + 2.0 / 3.0, 100.0)));
+        assert_eq!(curve.position(8), Some(Pos2::new(4.0, 100.0)));
+
+        let mut curve = Curve::alternating();
+        curve.stretch_range(1.0, 3.0, 0.5).unwrap();
+        assert!(curve.validate().is_ok());
+        assert_eq!(curve.position(4), Some(Pos2::new(1.5, 100.0)));
+        assert_eq!(curve.position(6), Some(Pos2::new(2.0, 0.0)));
+    }
+
+    #[test]
+    fn stretching_needs_room_after_the_range() {
+        let original = Curve::alternating();
+        for (from, to, factor) in [
+            // Nothing is left after the range to make room
+            (3.0, 4.0, 0.5),
+            (0.0, 4.0, 0.5),
+            (1.0, 3.0, 1.5),
+            (1.0, 3.0, 0.0),
+            (1.0, 3.0, f32::NAN),
+        ] {
+            let mut curve = original.clone();
+            let error = curve.stretch_range(from, to, factor).unwrap_err();
+            assert_eq!(
+                format!("{error:?}"),
+                format!("Stretch({factor:?})"),
+                "{from}..{to}"
+            );
+            assert_eq!(curve, original, "{from}..{to}");
+        }
+
+        // Not stretching at all is always possible
+        let mut curve = original.clone();
+        curve.stretch_range(3.0, 4.0, 1.0).unwrap();
+        assert_eq!(curve, original);
+    }
+}
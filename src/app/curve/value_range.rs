@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Maps the normalized curve values `0.0..=1.0` to output values
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValueRange {
+    pub min: f32,
+    pub max: f32,
+    /// Label shown next to values, empty for unitless values
+    #[serde(default)]
+    pub unit: String,
+    /// Equal distances on the curve are equal ratios of the output value
+    #[serde(default)]
+    pub logarithmic: bool,
+}
+
+impl Default for ValueRange {
+    fn default() -> Self {
+        Self::new(0.0, 100.0, "")
+    }
+}
+
+impl ValueRange {
+    pub fn new(min: f32, max: f32, unit: &str) -> Self {
+        Self {
+            min,
+            max,
+            unit: unit.to_owned(),
+            logarithmic: false,
+        }
+    }
+
+    pub fn logarithmic(mut self) -> Self {
+        self.logarithmic = true;
+        self
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.min.is_finite()
+            && self.max.is_finite()
+            && self.min != self.max
+            && (!self.logarithmic || (self.min > 0.0 && self.max > 0.0))
+    }
+
+    pub fn value(&self, normalized: f32) -> f32 {
+        if self.logarithmic {
+            self.min * (self.max / self.min).powf(normalized)
+        } else {
+            self.min + (self.max - self.min) * normalized
+        }
+    }
+
+    pub fn normalized(&self, value: f32) -> f32 {
+        if self.logarithmic {
+            (value / self.min).ln() / (self.max / self.min).ln()
+        } else {
+            (value - self.min) / (self.max - self.min)
+        }
+    }
+
+    /// Round values in the range to draw grid lines at
+    pub fn grid_values(&self) -> Vec<f32> {
+        let (low, high) = (self.min.min(self.max), self.min.max(self.max));
+        let steps: Vec<f32> = if self.logarithmic {
+            // 1, 2 and 5 of every decade, only powers of ten for wide ranges
+            let decades = (high / low).log10();
+            let multiples: &[f32] = if decades > 3.5 {
+                &[1.0]
+            } else {
+                &[1.0, 2.0, 5.0]
+            };
+            let first = low.log10().floor() as i32;
+            let last = high.log10().ceil() as i32;
+            (first..=last)
+                .flat_map(|exponent| {
+                    multiples
+                        .iter()
+                        .map(move |multiple| multiple * 10.0f32.powi(exponent))
+                })
+                .collect()
+        } else {
+            let step = nice_step((high - low) / 8.0);
+            let first = (low / step).ceil() as i32;
+            let last = (high / step).floor() as i32;
+            (first..=last).map(|i| i as f32 * step).collect()
+        };
+        steps
+            .into_iter()
+            .filter(|value| (low..=high).contains(value))
+            .collect()
+    }
+
+    pub fn format(&self, value: f32) -> String {
+        let decimals = if value.abs() >= 100.0 {
+            0
+        } else if value.abs() >= 10.0 {
+            1
+        } else {
+            2
+        };
+        let mut text = format!("{value:.decimals$}");
+        if text.contains('.') {
+            text.truncate(text.trim_end_matches('0').trim_end_matches('.').len());
+        }
+        if !self.unit.is_empty() {
+            text.push(' ');
+            text.push_str(&self.unit);
+        }
+        text
+    }
+}
+
+impl fmt::Display for ValueRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.format(self.min), self.format(self.max))?;
+        if self.logarithmic {
+            write!(f, " (log)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Rounds `step` up to 1, 2, 2.5 or 5 times a power of ten
+fn nice_step(step: f32) -> f32 {
+    let magnitude = 10.0f32.powf(step.log10().floor());
+    [1.0, 2.0, 2.5, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|nice| *nice >= step)
+        .unwrap_or(10.0 * magnitude)
+}
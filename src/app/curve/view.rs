@@ -0,0 +1,71 @@
+use super::Curve;
+use egui::{Pos2, Rect, Vec2};
+
+/// Zoom level can not go deeper than this many times the whole loop
+const MAX_ZOOM: f32 = 64.0;
+/// Fitting a flat curve still shows this much of the value axis
+const MIN_FIT_HEIGHT: f32 = 10.0;
+
+/// Part of a curve shown by `Curve::draw`
+///
+/// Zoom 1.0 shows the whole loop and all values, the view never leaves the loop.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct View {
+    zoom: Vec2,
+    /// Top left corner of the visible part in curve coordinates
+    offset: Pos2,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            zoom: Vec2::splat(1.0),
+            offset: Pos2::ZERO,
+        }
+    }
+}
+
+impl View {
+    /// Shows the whole loop, zoomed in on the values the points use
+    pub fn fit(curve: &Curve) -> Self {
+        let (min, max) = curve
+            .points
+            .iter()
+            .map(|point| point.pos().y)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), y| {
+                (min.min(y), max.max(y))
+            });
+        // A small margin keeps the outermost points clickable
+        let height = ((max - min) * 1.1).max(MIN_FIT_HEIGHT);
+        Self {
+            zoom: Vec2::new(1.0, (100.0 / height).clamp(1.0, MAX_ZOOM)),
+            offset: Pos2::new(0.0, (min + max - height) / 2.0),
+        }
+    }
+
+    /// Visible part of a loop of `length` beats in curve coordinates
+    pub fn visible(&self, length: f32) -> Rect {
+        let size = Vec2::new(length / self.zoom.x, 100.0 / self.zoom.y);
+        let min = Pos2::new(
+            self.offset.x.clamp(0.0, length - size.x),
+            self.offset.y.clamp(0.0, 100.0 - size.y),
+        );
+        Rect::from_min_size(min, size)
+    }
+
+    /// Zooms by `factor` per axis, keeping `center` at the same place on the screen
+    pub fn zoom(&mut self, length: f32, factor: Vec2, center: Pos2) {
+        let visible = self.visible(length);
+        let relative = (center - visible.min) / visible.size();
+        self.zoom = (self.zoom * factor).clamp(Vec2::splat(1.0), Vec2::splat(MAX_ZOOM));
+        let size = Vec2::new(length / self.zoom.x, 100.0 / self.zoom.y);
+        self.offset = center - relative * size;
+        self.offset = self.visible(length).min;
+    }
+
+    /// Moves the visible part by `delta` in curve coordinates
+    pub fn pan(&mut self, length: f32, delta: Vec2) {
+        self.offset = self.visible(length).min + delta;
+        self.offset = self.visible(length).min;
+    }
+}
@@ -0,0 +1,150 @@
+/// Undo steps kept before the oldest ones are dropped
+const LIMIT: usize = 100;
+
+/// Undo and redo stacks of snapshots of the edited state
+///
+/// Instead of tracking every single edit the state is compared to the last recorded one once
+/// per frame. A drag only becomes one step after the pointer is released.
+pub struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    /// State after the last recorded step, `None` before the first frame
+    recorded: Option<T>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            recorded: None,
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    /// Records a step if `state` changed since the last call and no edit is in progress
+    pub fn record(&mut self, state: &T, editing: bool) {
+        match &self.recorded {
+            Some(recorded) if recorded == state || editing => {}
+            _ => {
+                if let Some(recorded) = self.recorded.replace(state.clone()) {
+                    self.undo.push(recorded);
+                    if self.undo.len() > LIMIT {
+                        self.undo.remove(0);
+                    }
+                    self.redo.clear();
+                }
+            }
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo(&mut self, state: &mut T) {
+        // An edit still in progress is undone as a whole
+        self.record(state, false);
+        if let Some(previous) = self.undo.pop() {
+            self.redo.push(std::mem::replace(state, previous));
+            self.recorded = Some(state.clone());
+        }
+    }
+
+    pub fn redo(&mut self, state: &mut T) {
+        // A new edit makes the redo steps obsolete
+        self.record(state, false);
+        if let Some(next) = self.redo.pop() {
+            self.undo.push(std::mem::replace(state, next));
+            self.recorded = Some(state.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// History after recording every state of `states` as its own step
+    fn recorded(states: impl IntoIterator<Item = u32>) -> History<u32> {
+        let mut history = History::default();
+        for state in states {
+            history.record(&state, false);
+        }
+        history
+    }
+
+    #[test]
+    fn undo_and_redo_steps() {
+        let mut history = recorded([0, 1, 2]);
+        let mut state = 2;
+        // Unchanged states are not recorded again
+        history.record(&state, false);
+
+        history.undo(&mut state);
+        assert_eq!(state, 1);
+        history.undo(&mut state);
+        assert_eq!(state, 0);
+        assert!(!history.can_undo());
+        history.undo(&mut state);
+        assert_eq!(state, 0);
+
+        history.redo(&mut state);
+        history.redo(&mut state);
+        assert_eq!(state, 2);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn new_steps_clear_redo() {
+        let mut history = recorded([0, 1]);
+        let mut state = 1;
+        history.undo(&mut state);
+        assert!(history.can_redo());
+
+        state = 5;
+        history.record(&state, false);
+        assert!(!history.can_redo());
+        history.undo(&mut state);
+        assert_eq!(state, 0);
+    }
+
+    #[test]
+    fn edits_in_progress_become_one_step() {
+        let mut history = recorded([0]);
+        for state in 1..10 {
+            history.record(&state, true);
+        }
+        history.record(&10, false);
+
+        let mut state = 10;
+        history.undo(&mut state);
+        assert_eq!(state, 0);
+        assert!(!history.can_undo());
+
+        // Undoing during an edit undoes the edit so far
+        let mut history = recorded([0]);
+        let mut state = 3;
+        history.record(&state, true);
+        history.undo(&mut state);
+        assert_eq!(state, 0);
+        history.redo(&mut state);
+        assert_eq!(state, 3);
+    }
+
+    #[test]
+    fn oldest_steps_are_dropped() {
+        let last = LIMIT as u32 + 10;
+        let mut history = recorded(0..=last);
+        let mut state = last;
+        while history.can_undo() {
+            history.undo(&mut state);
+        }
+        assert_eq!(state, 10);
+    }
+}
@@ -0,0 +1,53 @@
+use super::{
+    curve::Curve,
+    output::{cc::CcRoute, osc::OscRoute},
+};
+use serde::{Deserialize, Serialize};
+
+/// A named curve animating one parameter, all lanes share the playhead
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Lane {
+    pub name: String,
+    pub curve: Curve,
+    /// While any lane is soloed only soloed lanes are played
+    #[serde(default)]
+    pub solo: bool,
+    #[serde(default)]
+    pub mute: bool,
+    /// Where the values are sent as MIDI control changes
+    #[serde(default)]
+    pub cc: Option<CcRoute>,
+    /// Where the values are sent as OSC messages
+    #[serde(default)]
+    pub osc: Option<OscRoute>,
+}
+
+impl Lane {
+    pub fn new(name: impl Into<String>, curve: Curve) -> Self {
+        Self {
+            name: name.into(),
+            curve,
+            solo: false,
+            mute: false,
+            cc: None,
+            osc: None,
+        }
+    }
+}
+
+/// Whether `lane` is played, taking solo and mute of all `lanes` into account
+pub fn is_audible(lanes: &[Lane], lane: &Lane) -> bool {
+    if lanes.iter().any(|lane| lane.solo) {
+        lane.solo
+    } else {
+        !lane.mute
+    }
+}
+
+/// A name not used by any of `lanes` yet
+pub fn unused_name(lanes: &[Lane]) -> String {
+    (1..)
+        .map(|i| format!("Lane {i}"))
+        .find(|name| lanes.iter().all(|lane| lane.name != *name))
+        .expect("Ran out of lane names")
+}
@@ -0,0 +1,190 @@
+pub mod cc;
+pub mod osc;
+
+use super::{curve::Curve, transport::Transport};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Values sent per second
+pub const MIN_RATE: f32 = 1.0;
+pub const MAX_RATE: f32 = 1000.0;
+
+/// Curves with where their values go
+pub type Routes<R> = Vec<(Curve, R)>;
+
+/// Turns the values of routed curves into messages and sends them
+pub trait Sender: Send + 'static {
+    /// Where and how the values of one curve are sent
+    type Route: Clone + Send + 'static;
+
+    /// Called `rate` times per second while the transport plays
+    fn send(&mut self, beat: f64, routes: &[(Curve, Self::Route)]) -> io::Result<()>;
+}
+
+/// Sends the values of curves on its own thread while the transport plays
+///
+/// The UI hands over copies of the curves, so sending keeps its pace no matter how often the UI
+/// is repainted.
+pub struct Output<S: Sender> {
+    routes: Arc<Mutex<Routes<S::Route>>>,
+    rate: Arc<Mutex<f32>>,
+    stop: Arc<AtomicBool>,
+    error: Arc<Mutex<Option<String>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<S: Sender> Output<S> {
+    /// Fails where there are no threads, like in browsers
+    pub fn start(mut sender: S, transport: Transport, rate: f32) -> io::Result<Self> {
+        let routes = Arc::new(Mutex::new(Vec::new()));
+        let rate = Arc::new(Mutex::new(rate.clamp(MIN_RATE, MAX_RATE)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let error = Arc::new(Mutex::new(None));
+
+        let thread = {
+            let (routes, rate, stop, error) =
+                (routes.clone(), rate.clone(), stop.clone(), error.clone());
+            std::thread::Builder::new()
+                .name("output".to_owned())
+                .spawn(move || {
+                    let mut next = Instant::now();
+                    while !stop.load(Ordering::Relaxed) {
+                        if transport.is_playing() {
+                            let routes = routes.lock().expect("Output routes poisoned").clone();
+                            if let Err(err) = sender.send(transport.position(), &routes) {
+                                log::warn!("Could not send values: {err}");
+                                *error.lock().expect("Output error poisoned") =
+                                    Some(err.to_string());
+                                break;
+                            }
+                        }
+
+                        // Late ticks are dropped instead of being sent in a burst
+                        let interval = 1.0 / *rate.lock().expect("Output rate poisoned");
+                        next = (next + Duration::from_secs_f32(interval)).max(Instant::now());
+                        // Parked instead of asleep, so stopping does not wait for the next tick
+                        while !stop.load(Ordering::Relaxed) && Instant::now() < next {
+                            std::thread::park_timeout(
+                                next.saturating_duration_since(Instant::now()),
+                            );
+                        }
+                    }
+                })?
+        };
+
+        Ok(Self {
+            routes,
+            rate,
+            stop,
+            error,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn set_routes(&self, routes: Routes<S::Route>) {
+        *self.routes.lock().expect("Output routes poisoned") = routes;
+    }
+
+    pub fn set_rate(&self, rate: f32) {
+        if rate.is_finite() {
+            *self.rate.lock().expect("Output rate poisoned") = rate.clamp(MIN_RATE, MAX_RATE);
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .map_or(false, |thread| !thread.is_finished())
+    }
+
+    /// Why sending stopped
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().expect("Output error poisoned").clone()
+    }
+}
+
+/// Waits for the thread, so devices it opened are closed and can be opened again right away
+impl<S: Sender> Drop for Output<S> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            if thread.join().is_err() {
+                log::warn!("Output thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts how often it sends, fails once `fail_after` sends are done
+    struct Counting {
+        sent: Arc<Mutex<usize>>,
+        fail_after: usize,
+    }
+
+    impl Sender for Counting {
+        type Route = ();
+
+        fn send(&mut self, _beat: f64, _routes: &[(Curve, ())]) -> io::Result<()> {
+            let mut sent = self.sent.lock().unwrap();
+            if *sent == self.fail_after {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Device is gone"));
+            }
+            *sent += 1;
+            Ok(())
+        }
+    }
+
+    fn start(fail_after: usize) -> (Arc<Mutex<usize>>, Output<Counting>) {
+        let sent = Arc::new(Mutex::new(0));
+        let transport = Transport::default();
+        transport.play();
+        let sender = Counting {
+            sent: sent.clone(),
+            fail_after,
+        };
+        (sent, Output::start(sender, transport, MIN_RATE).unwrap())
+    }
+
+    /// Waits up to five seconds for `condition`
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn dropping_stops_without_waiting_for_the_next_tick() {
+        let (sent, output) = start(usize::MAX);
+        wait_for(|| *sent.lock().unwrap() > 0);
+        assert!(output.is_running());
+
+        let dropped = Instant::now();
+        drop(output);
+        // A tick is a second long at the lowest rate
+        assert!(dropped.elapsed() < Duration::from_millis(500));
+        // The sender is gone, and with it every device it opened
+        assert_eq!(Arc::strong_count(&sent), 1);
+    }
+
+    #[test]
+    fn errors_stop_sending() {
+        let (sent, output) = start(0);
+        wait_for(|| !output.is_running());
+        assert_eq!(output.error().as_deref(), Some("Device is gone"));
+        assert_eq!(*sent.lock().unwrap(), 0);
+    }
+}
@@ -0,0 +1,290 @@
+use super::Sender;
+use crate::curve::Curve;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+};
+
+const CONTROL_CHANGE: u8 = 0xb0;
+/// Controllers selecting a non registered parameter and setting its value
+const NRPN_MSB: u8 = 99;
+const NRPN_LSB: u8 = 98;
+const DATA_ENTRY_MSB: u8 = 6;
+const DATA_ENTRY_LSB: u8 = 38;
+
+/// What a curve controls on the receiving device
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Controller {
+    /// Control change with 7 bit values, `0..=127`
+    Cc(u8),
+    /// Non registered parameter number with 14 bit values, `0..=16383`
+    Nrpn(u16),
+}
+
+impl fmt::Display for Controller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Controller::Cc(number) => write!(f, "CC {number}"),
+            Controller::Nrpn(number) => write!(f, "NRPN {number}"),
+        }
+    }
+}
+
+impl Controller {
+    /// Highest value of the resolution
+    pub fn max_value(self) -> u16 {
+        match self {
+            Controller::Cc(_) => 0x7f,
+            Controller::Nrpn(_) => 0x3fff,
+        }
+    }
+
+    /// Value for a curve value normalized to `0.0..=1.0`
+    pub fn quantize(self, normalized: f32) -> u16 {
+        let max = self.max_value();
+        if normalized.is_finite() {
+            (normalized.clamp(0.0, 1.0) * max as f32).round() as u16
+        } else {
+            0
+        }
+    }
+
+    /// MIDI bytes setting the controller to `value` on `channel` in `0..=15`
+    pub fn encode(self, channel: u8, value: u16) -> Vec<u8> {
+        let status = CONTROL_CHANGE | channel & 0x0f;
+        let value = value.min(self.max_value());
+        match self {
+            Controller::Cc(number) => vec![status, number & 0x7f, value as u8],
+            Controller::Nrpn(number) => {
+                let number = number & 0x3fff;
+                vec![
+                    status,
+                    NRPN_MSB,
+                    (number >> 7) as u8,
+                    status,
+                    NRPN_LSB,
+                    (number & 0x7f) as u8,
+                    status,
+                    DATA_ENTRY_MSB,
+                    (value >> 7) as u8,
+                    status,
+                    DATA_ENTRY_LSB,
+                    (value & 0x7f) as u8,
+                ]
+            }
+        }
+    }
+}
+
+/// Where the values of a curve are sent as MIDI control changes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CcRoute {
+    /// Raw MIDI device, e.g. a virtual port of `snd-virmidi`
+    pub device: String,
+    /// `0..=15`, shown as `1..=16`
+    pub channel: u8,
+    pub controller: Controller,
+}
+
+impl Default for CcRoute {
+    fn default() -> Self {
+        Self {
+            device: Default::default(),
+            channel: 0,
+            // Modulation wheel
+            controller: Controller::Cc(1),
+        }
+    }
+}
+
+/// Sends control changes to raw MIDI devices, only for values that changed
+#[derive(Debug, Default)]
+pub struct CcSender {
+    /// Last value sent to every device, channel and controller
+    sent: HashMap<(String, u8, Controller), u16>,
+    devices: HashMap<String, File>,
+}
+
+impl CcSender {
+    /// Bytes for every device whose values changed since they were last sent
+    pub fn messages(&mut self, beat: f64, routes: &[(Curve, CcRoute)]) -> Vec<(String, Vec<u8>)> {
+        // Routes added again later start with sending their value
+        self.sent.retain(|(device, channel, controller), _| {
+            routes.iter().any(|(_, route)| {
+                route.device == *device
+                    && route.channel == *channel
+                    && route.controller == *controller
+            })
+        });
+        let mut messages: Vec<(String, Vec<u8>)> = Vec::new();
+        for (curve, route) in routes {
+            let normalized = curve.range().normalized(curve.value(beat as f32));
+            let value = route.controller.quantize(normalized);
+            let key = (route.device.clone(), route.channel, route.controller);
+            if self.sent.insert(key, value) == Some(value) {
+                continue;
+            }
+
+            let bytes = route.controller.encode(route.channel, value);
+            match messages
+                .iter_mut()
+                .find(|(device, _)| *device == route.device)
+            {
+                Some((_, message)) => message.extend(bytes),
+                None => messages.push((route.device.clone(), bytes)),
+            }
+        }
+        messages
+    }
+}
+
+impl Sender for CcSender {
+    type Route = CcRoute;
+
+    fn send(&mut self, beat: f64, routes: &[(Curve, CcRoute)]) -> io::Result<()> {
+        // Devices no route uses any more are closed, so other programs can open them
+        self.devices
+            .retain(|device, _| routes.iter().any(|(_, route)| route.device == *device));
+        for (device, bytes) in self.messages(beat, routes) {
+            let file = match self.devices.get_mut(&device) {
+                Some(file) => file,
+                None => {
+                    let file = OpenOptions::new().write(true).open(&device)?;
+                    self.devices.entry(device).or_insert(file)
+                }
+            };
+            file.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(device: &str, controller: Controller) -> CcRoute {
+        CcRoute {
+            device: device.to_owned(),
+            channel: 2,
+            controller,
+        }
+    }
+
+    #[test]
+    fn control_change_bytes() {
+        assert_eq!(Controller::Cc(7).encode(0, 100), vec![0xb0, 7, 100]);
+        assert_eq!(Controller::Cc(74).encode(15, 0), vec![0xbf, 74, 0]);
+        // Values and numbers out of range are cut to 7 bits
+        assert_eq!(Controller::Cc(200).encode(2, 1000), vec![0xb2, 72, 127]);
+    }
+
+    #[test]
+    fn nrpn_bytes() {
+        let number = 3 << 7 | 5;
+        let value = 0x2a << 7 | 0x15;
+        assert_eq!(
+            Controller::Nrpn(number).encode(1, value),
+            // Parameter number first, then the value, both split into 7 bit halves
+            vec![0xb1, 99, 3, 0xb1, 98, 5, 0xb1, 6, 0x2a, 0xb1, 38, 0x15]
+        );
+        assert_eq!(
+            Controller::Nrpn(16383).encode(0, 16383),
+            vec![0xb0, 99, 127, 0xb0, 98, 127, 0xb0, 6, 127, 0xb0, 38, 127]
+        );
+    }
+
+    #[test]
+    fn quantize_clamps() {
+        assert_eq!(Controller::Cc(1).quantize(0.5), 64);
+        assert_eq!(Controller::Cc(1).quantize(1.0), 127);
+        assert_eq!(Controller::Cc(1).quantize(1.5), 127);
+        assert_eq!(Controller::Cc(1).quantize(-0.5), 0);
+        assert_eq!(Controller::Nrpn(1).quantize(1.0), 16383);
+        assert_eq!(Controller::Nrpn(1).quantize(0.25), 4096);
+        assert_eq!(Controller::Cc(1).quantize(f32::NAN), 0);
+        assert_eq!(Controller::Nrpn(1).quantize(f32::INFINITY), 0);
+    }
+
+    #[test]
+    fn unchanged_values_are_sent_once() {
+        let mut sender = CcSender::default();
+        let routes = vec![
+            (Curve::alternating(), route("a", Controller::Cc(1))),
+            (Curve::alternating(), route("a", Controller::Nrpn(1))),
+            (Curve::fixed(), route("b", Controller::Cc(1))),
+        ];
+
+        let messages = sender.messages(0.0, &routes);
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    "a".to_owned(),
+                    [
+                        Controller::Cc(1).encode(2, 0),
+                        Controller::Nrpn(1).encode(2, 0)
+                    ]
+                    .concat()
+                ),
+                ("b".to_owned(), Controller::Cc(1).encode(2, 127)),
+            ]
+        );
+        assert!(sender.messages(0.0, &routes).is_empty());
+
+        // Only the changed values are sent again
+        let messages = sender.messages(0.5, &routes);
+        assert_eq!(
+            messages,
+            vec![(
+                "a".to_owned(),
+                [
+                    Controller::Cc(1).encode(2, 64),
+                    Controller::Nrpn(1).encode(2, 8192)
+                ]
+                .concat()
+            )]
+        );
+    }
+
+    #[test]
+    fn removed_routes_are_forgotten() {
+        let mut sender = CcSender::default();
+        let routes = vec![
+            (Curve::fixed(), route("a", Controller::Cc(1))),
+            (Curve::fixed(), route("b", Controller::Cc(1))),
+        ];
+        sender.messages(0.0, &routes);
+        sender.messages(0.0, &routes[1..]);
+        // The value is sent again once the route is back
+        assert_eq!(
+            sender.messages(0.0, &routes),
+            vec![("a".to_owned(), Controller::Cc(1).encode(2, 127))]
+        );
+    }
+
+    #[test]
+    fn sends_to_the_device_file() {
+        // A plain file stands in for a virtual port
+        let path = std::env::temp_dir().join(format!("cc_output_{}", std::process::id()));
+        std::fs::write(&path, []).unwrap();
+        let device = path.display().to_string();
+        let routes = vec![(Curve::fixed(), route(&device, Controller::Cc(7)))];
+
+        let mut sender = CcSender::default();
+        sender.send(0.0, &routes).unwrap();
+        sender.send(1.0, &routes).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, vec![0xb2, 7, 127]);
+        assert!(sender.devices.contains_key(&device));
+
+        // Devices no route uses any more are closed
+        sender.send(0.0, &[]).unwrap();
+        assert!(sender.devices.is_empty());
+    }
+}
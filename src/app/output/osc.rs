@@ -0,0 +1,252 @@
+use super::Sender;
+use crate::curve::Curve;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Seconds between the NTP epoch in 1900 and the unix epoch
+const NTP_OFFSET: u64 = 2_208_988_800;
+
+/// Type of the argument a value is sent as
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArgumentType {
+    /// 32 bit float, the value as it is
+    #[default]
+    Float,
+    /// 32 bit integer, the value rounded
+    Int,
+}
+
+impl ArgumentType {
+    pub const ALL: [Self; 2] = [Self::Float, Self::Int];
+}
+
+impl fmt::Display for ArgumentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgumentType::Float => write!(f, "Float"),
+            ArgumentType::Int => write!(f, "Int"),
+        }
+    }
+}
+
+/// Where the values of a curve are sent as OSC messages
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscRoute {
+    /// Address pattern of the messages, starts with a slash
+    pub address: String,
+    pub argument: ArgumentType,
+}
+
+impl Default for OscRoute {
+    fn default() -> Self {
+        Self {
+            address: "/curve".to_owned(),
+            argument: ArgumentType::Float,
+        }
+    }
+}
+
+impl OscRoute {
+    /// Whether receivers accept the address
+    pub fn is_valid(&self) -> bool {
+        self.address.starts_with('/')
+            && !self.address.chars().any(|c| c == '\0' || c.is_whitespace())
+    }
+
+    /// Message carrying `value`
+    pub fn message(&self, value: f32) -> Vec<u8> {
+        let mut message = Vec::new();
+        push_string(&mut message, &self.address);
+        match self.argument {
+            ArgumentType::Float => {
+                push_string(&mut message, ",f");
+                message.extend(value.to_be_bytes());
+            }
+            ArgumentType::Int => {
+                push_string(&mut message, ",i");
+                // Saturates, not a number becomes 0
+                message.extend((value.round() as i32).to_be_bytes());
+            }
+        }
+        message
+    }
+}
+
+/// Null terminated and padded to a multiple of four bytes
+fn push_string(buffer: &mut Vec<u8>, string: &str) {
+    buffer.extend(string.as_bytes());
+    buffer.extend(std::iter::repeat(0).take(4 - string.len() % 4));
+}
+
+/// NTP timestamp of `time`, seconds since 1900 and fractions of a second in 32 bits each
+pub fn time_tag(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() + NTP_OFFSET;
+    let fraction = ((since_epoch.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    seconds << 32 | fraction
+}
+
+/// Bundle of `messages` to be applied at `time`
+pub fn bundle(time: SystemTime, messages: &[Vec<u8>]) -> Vec<u8> {
+    let mut bundle = Vec::new();
+    push_string(&mut bundle, "#bundle");
+    bundle.extend(time_tag(time).to_be_bytes());
+    for message in messages {
+        bundle.extend((message.len() as i32).to_be_bytes());
+        bundle.extend(message);
+    }
+    bundle
+}
+
+/// Sends the values of all curves in one timestamped bundle over UDP
+///
+/// Values are sent on every tick, receivers of OSC usually expect a steady stream.
+#[derive(Debug)]
+pub struct OscSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+}
+
+impl OscSender {
+    /// `target` is a `host:port`
+    pub fn new(target: &str) -> io::Result<Self> {
+        let target = target.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("Unknown host: {target}"))
+        })?;
+        let local: SocketAddr = if target.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0; 8], 0).into()
+        };
+        Ok(Self {
+            socket: UdpSocket::bind(local)?,
+            target,
+        })
+    }
+
+    /// Bundle with the values of all curves at `beat`, `None` without valid routes
+    pub fn bundle(&self, beat: f64, routes: &[(Curve, OscRoute)]) -> Option<Vec<u8>> {
+        let messages: Vec<Vec<u8>> = routes
+            .iter()
+            .filter(|(_, route)| route.is_valid())
+            .map(|(curve, route)| route.message(curve.value(beat as f32)))
+            .collect();
+        (!messages.is_empty()).then(|| bundle(SystemTime::now(), &messages))
+    }
+}
+
+impl Sender for OscSender {
+    type Route = OscRoute;
+
+    fn send(&mut self, beat: f64, routes: &[(Curve, OscRoute)]) -> io::Result<()> {
+        if let Some(bundle) = self.bundle(beat, routes) {
+            self.socket.send_to(&bundle, self.target)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn route(address: &str, argument: ArgumentType) -> OscRoute {
+        OscRoute {
+            address: address.to_owned(),
+            argument,
+        }
+    }
+
+    #[test]
+    fn strings_are_padded() {
+        let padded = |string| {
+            let mut buffer = Vec::new();
+            push_string(&mut buffer, string);
+            buffer
+        };
+        // Always null terminated, so multiples of four get four more bytes
+        assert_eq!(padded("/abc"), b"/abc\0\0\0\0");
+        assert_eq!(padded(""), b"\0\0\0\0");
+        assert_eq!(padded("/ab"), b"/ab\0");
+        assert_eq!(padded("/abcd"), b"/abcd\0\0\0");
+    }
+
+    #[test]
+    fn argument_types() {
+        assert_eq!(
+            route("/abc", ArgumentType::Float).message(0.5),
+            [b"/abc\0\0\0\0,f\0\0".as_slice(), &0.5f32.to_be_bytes()].concat()
+        );
+        assert_eq!(
+            route("/abc", ArgumentType::Int).message(-2.6),
+            [b"/abc\0\0\0\0,i\0\0".as_slice(), &(-3i32).to_be_bytes()].concat()
+        );
+        assert_eq!(
+            route("/x", ArgumentType::Int).message(f32::NAN),
+            b"/x\0\0,i\0\0\0\0\0\0".to_vec()
+        );
+    }
+
+    #[test]
+    fn addresses() {
+        assert!(OscRoute::default().is_valid());
+        assert!(!route("curve", ArgumentType::Float).is_valid());
+        assert!(!route("/a b", ArgumentType::Float).is_valid());
+    }
+
+    #[test]
+    fn time_tags_count_from_1900() {
+        assert_eq!(time_tag(UNIX_EPOCH), NTP_OFFSET << 32);
+        // Fractions are in units of 2^-32 seconds
+        let time = UNIX_EPOCH + Duration::from_secs(10) + Duration::from_millis(250);
+        assert_eq!(time_tag(time), (NTP_OFFSET + 10) << 32 | 1 << 30);
+        // 1970-01-01 is 2208988800 seconds after 1900-01-01
+        assert_eq!(NTP_OFFSET, (70 * 365 + 17) * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn bundles_arrive_at_a_listener() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let mut sender = OscSender::new(&target).unwrap();
+
+        let routes = vec![
+            (Curve::alternating(), route("/a", ArgumentType::Float)),
+            (Curve::fixed(), route("/b", ArgumentType::Int)),
+            (Curve::fixed(), route("invalid", ArgumentType::Int)),
+        ];
+        let before = time_tag(SystemTime::now());
+        sender.send(0.5, &routes).unwrap();
+        let after = time_tag(SystemTime::now());
+
+        let mut buffer = [0; 256];
+        let length = listener.recv(&mut buffer).unwrap();
+        let received = &buffer[..length];
+        assert_eq!(&received[..8], b"#bundle\0");
+        let time = u64::from_be_bytes(received[8..16].try_into().unwrap());
+        assert!((before..=after).contains(&time));
+
+        let first = route("/a", ArgumentType::Float).message(50.0);
+        let second = route("/b", ArgumentType::Int).message(100.0);
+        let elements = [
+            (first.len() as i32).to_be_bytes().as_slice(),
+            &first,
+            &(second.len() as i32).to_be_bytes(),
+            &second,
+        ]
+        .concat();
+        assert_eq!(&received[16..], elements);
+
+        // Nothing is sent without valid routes
+        assert_eq!(sender.bundle(0.5, &routes[2..]), None);
+    }
+}
@@ -0,0 +1,213 @@
+use super::curve::{Curve, FileError};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Identifies preset packs, so curve files and other JSON files are rejected early
+pub(super) const FORMAT: &str = "ui_experiments/presets";
+/// Bump whenever the pack layout changes, the curves have their own version
+pub(super) const VERSION: u32 = 1;
+
+/// A curve saved under a name to be reused in other lanes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    /// Empty for presets outside of any folder
+    #[serde(default)]
+    pub folder: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub curve: Curve,
+}
+
+impl Preset {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own| own.eq_ignore_ascii_case(tag))
+    }
+}
+
+/// Presets exchanged as one file
+#[derive(Serialize, Deserialize)]
+struct PresetPack {
+    format: String,
+    version: u32,
+    presets: Vec<Preset>,
+}
+
+/// Splits comma separated tags, dropping empty ones
+pub fn parse_tags(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Adds `preset` to `presets`, replacing the one with the same name in the same folder
+pub fn insert(presets: &mut Vec<Preset>, preset: Preset) {
+    match presets
+        .iter_mut()
+        .find(|old| old.name == preset.name && old.folder == preset.folder)
+    {
+        Some(old) => *old = preset,
+        None => presets.push(preset),
+    }
+}
+
+/// All folders in use, sorted
+pub fn folders(presets: &[Preset]) -> Vec<&str> {
+    let mut folders: Vec<&str> = presets
+        .iter()
+        .map(|preset| preset.folder.as_str())
+        .collect();
+    folders.sort_unstable();
+    folders.dedup();
+    folders
+}
+
+/// All tags in use, sorted
+///
+/// Tags only differing in case are the same for `Preset::has_tag`, only the first spelling is
+/// listed.
+pub fn tags(presets: &[Preset]) -> Vec<&str> {
+    let mut tags: Vec<&str> = presets
+        .iter()
+        .flat_map(|preset| preset.tags.iter().map(String::as_str))
+        .collect();
+    tags.sort_by_cached_key(|tag| tag.to_ascii_lowercase());
+    tags.dedup_by(|tag, other| tag.eq_ignore_ascii_case(other));
+    tags
+}
+
+pub fn load_pack(path: impl AsRef<Path>) -> Result<Vec<Preset>, FileError> {
+    let pack: PresetPack = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if pack.format != FORMAT {
+        return Err(FileError::Format(pack.format));
+    }
+    if pack.version > VERSION {
+        return Err(FileError::Version(pack.version));
+    }
+    Ok(pack.presets)
+}
+
+pub fn save_pack(presets: &[Preset], path: impl AsRef<Path>) -> Result<(), FileError> {
+    let pack = PresetPack {
+        format: FORMAT.to_owned(),
+        version: VERSION,
+        presets: presets.to_vec(),
+    };
+    let json = serde_json::to_string_pretty(&pack).expect("Could not serialize presets");
+    Ok(fs::write(path, json)?)
+}
+
+/// Unsaved input of the preset window
+#[derive(Default)]
+pub struct PresetForm {
+    pub name: String,
+    pub folder: String,
+    /// Comma separated
+    pub tags: String,
+    /// Only presets with this tag are listed
+    pub tag_filter: Option<String>,
+    /// Outcome of the last import or export
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str, folder: &str, tags: &[&str]) -> Preset {
+        Preset {
+            name: name.to_owned(),
+            folder: folder.to_owned(),
+            tags: tags.iter().map(|tag| (*tag).to_owned()).collect(),
+            curve: Curve::default(),
+        }
+    }
+
+    /// Temporary pack file named after the test
+    fn pack_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("presets_{name}_{}.json", std::process::id()))
+    }
+
+    #[test]
+    fn insert_replaces_by_name_and_folder() {
+        let mut presets = vec![preset("Ramp", "", &[]), preset("Ramp", "Slow", &[])];
+        insert(&mut presets, preset("Ramp", "Slow", &["new"]));
+        insert(&mut presets, preset("Ramp", "Fast", &[]));
+        insert(&mut presets, preset("ramp", "", &[]));
+
+        assert_eq!(presets.len(), 4);
+        assert_eq!(presets[1].tags, vec!["new".to_owned()]);
+        assert_eq!(folders(&presets), vec!["", "Fast", "Slow"]);
+    }
+
+    #[test]
+    fn tags_are_trimmed_and_compared_ignoring_case() {
+        assert_eq!(
+            parse_tags(" slow, Bass ,,  , wobble"),
+            vec!["slow".to_owned(), "Bass".to_owned(), "wobble".to_owned()]
+        );
+        assert!(parse_tags(" , ").is_empty());
+
+        let presets = vec![
+            preset("A", "", &["bass", "Slow"]),
+            preset("B", "", &["Bass", "fast"]),
+        ];
+        assert_eq!(tags(&presets), vec!["bass", "fast", "Slow"]);
+        assert!(presets[1].has_tag("BASS"));
+        assert!(!presets[1].has_tag("slow"));
+    }
+
+    #[test]
+    fn saved_packs_load_unchanged() {
+        let presets = vec![
+            preset("Sine", "LFO", &["smooth"]),
+            Preset {
+                curve: Curve::forward(),
+                ..preset("Saw", "", &[])
+            },
+        ];
+        let path = pack_path("round_trip");
+        save_pack(&presets, &path).unwrap();
+        let loaded = load_pack(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), presets);
+    }
+
+    #[test]
+    fn other_formats_and_newer_versions_are_rejected() {
+        let load = |name, document: &str| {
+            let path = pack_path(name);
+            std::fs::write(&path, document).unwrap();
+            let loaded = load_pack(&path);
+            std::fs::remove_file(&path).unwrap();
+            loaded
+        };
+
+        let curve = load("curve", &Curve::default().to_json());
+        assert!(matches!(curve, Err(FileError::Json(_))));
+
+        let other = load(
+            "format",
+            r#"{"format": "ui_experiments/curve", "version": 1, "presets": []}"#,
+        );
+        assert!(
+            matches!(other, Err(FileError::Format(format)) if format == "ui_experiments/curve")
+        );
+
+        let newer = load(
+            "version",
+            &format!(
+                r#"{{"format": "{FORMAT}", "version": {}, "presets": []}}"#,
+                VERSION + 1
+            ),
+        );
+        assert!(matches!(newer, Err(FileError::Version(version)) if version == VERSION + 1));
+
+        assert!(matches!(
+            load_pack(pack_path("missing")),
+            Err(FileError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,358 @@
+pub mod midi;
+pub mod tempo;
+
+use self::tempo::Tempo;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// Time source of a [`Transport`]
+pub trait Clock: Send + Sync {
+    /// Time since any fixed point, never decreases
+    fn now(&self) -> Duration;
+}
+
+/// Monotonic clock of the system, starting when it is created
+#[derive(Debug)]
+pub struct SystemClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    // `Instant` is not available in browsers, the wall clock may jump there
+    #[cfg(target_arch = "wasm32")]
+    start: chrono::NaiveDateTime,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+            #[cfg(target_arch = "wasm32")]
+            start: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn now(&self) -> Duration {
+        (chrono::Utc::now().naive_utc() - self.start)
+            .to_std()
+            .unwrap_or_default()
+    }
+}
+
+/// Clock which only moves when told to, for deterministic playback
+#[derive(Debug, Default)]
+pub struct MockClock(Mutex<Duration>);
+
+impl MockClock {
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().expect("Mock clock poisoned") += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.0.lock().expect("Mock clock poisoned")
+    }
+}
+
+#[derive(Clone, Debug)]
+struct State {
+    tempo: Tempo,
+    playing: bool,
+    /// Position while not playing
+    position: f64,
+    /// Beats played over and over, the playhead may start before them
+    loop_region: (f64, f64),
+}
+
+/// Playhead moving through the beats at the speed of its tempo
+///
+/// The position is derived from the clock whenever it is asked for, so it is the same for every
+/// thread and does not depend on how often anybody looks. Clones share the same playhead.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "Tempo", from = "Tempo")]
+pub struct Transport {
+    clock: Arc<dyn Clock>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock::default()), Tempo::default())
+    }
+}
+
+/// Only the tempo is saved, the transport is stopped after loading
+impl From<Tempo> for Transport {
+    fn from(tempo: Tempo) -> Self {
+        Self::new(Arc::new(SystemClock::default()), tempo)
+    }
+}
+
+impl From<Transport> for Tempo {
+    fn from(transport: Transport) -> Self {
+        transport.state().tempo.clone()
+    }
+}
+
+impl Transport {
+    pub fn new(clock: Arc<dyn Clock>, tempo: Tempo) -> Self {
+        Self {
+            clock,
+            state: Arc::new(Mutex::new(State {
+                tempo,
+                playing: false,
+                position: 0.0,
+                loop_region: (0.0, 4.0),
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("Transport state poisoned")
+    }
+
+    fn now(&self) -> f64 {
+        self.clock.now().as_secs_f64()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state().playing
+    }
+
+    /// Beat position of the playhead
+    pub fn position(&self) -> f64 {
+        let now = self.now();
+        Self::position_locked(&self.state(), now)
+    }
+
+    /// Position while already holding the lock, so nobody can seek in between
+    ///
+    /// Positions past the loop region wrap into it while paused as well, like they will once
+    /// playing.
+    fn position_locked(state: &State, now: f64) -> f64 {
+        let beat = if state.playing {
+            state.tempo.beat(now)
+        } else {
+            state.position
+        };
+        let (start, end) = state.loop_region;
+        if beat < end {
+            beat
+        } else {
+            start + (beat - start).rem_euclid(end - start)
+        }
+    }
+
+    /// Continues from the current position
+    pub fn play(&self) {
+        let now = self.now();
+        let mut state = self.state();
+        if !state.playing {
+            let position = state.position;
+            state.tempo.set_beat(now, position);
+            state.playing = true;
+        }
+    }
+
+    /// Stops, keeping the position
+    pub fn pause(&self) {
+        let now = self.now();
+        let mut state = self.state();
+        state.position = Self::position_locked(&state, now);
+        state.playing = false;
+    }
+
+    /// Stops and goes back to the start of the loop region
+    pub fn stop(&self) {
+        let mut state = self.state();
+        state.playing = false;
+        state.position = state.loop_region.0;
+    }
+
+    /// Moves the playhead to `beat`, it keeps playing from there if it was
+    pub fn seek(&self, beat: f64) {
+        if !beat.is_finite() {
+            return;
+        }
+        let now = self.now();
+        let mut state = self.state();
+        state.position = beat.max(0.0);
+        let position = state.position;
+        state.tempo.set_beat(now, position);
+    }
+
+    /// Makes now the start of the loop region, like pressing play on the downbeat
+    pub fn restart(&self) {
+        let start = self.state().loop_region.0;
+        self.seek(start);
+    }
+
+    pub fn loop_region(&self) -> (f64, f64) {
+        self.state().loop_region
+    }
+
+    /// Plays the beats `start..end` over and over, ignored if the region is empty
+    pub fn set_loop_region(&self, start: f64, end: f64) {
+        if !(start.is_finite() && end.is_finite() && 0.0 <= start && start < end) {
+            return;
+        }
+        let now = self.now();
+        let mut state = self.state();
+        if state.loop_region == (start, end) {
+            return;
+        }
+        // Keep the playhead where it is, even if it was wrapped around the old region
+        let position = Self::position_locked(&state, now);
+        state.loop_region = (start, end);
+        state.position = position;
+        state.tempo.set_beat(now, position);
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.state().tempo.bpm()
+    }
+
+    pub fn set_bpm(&self, bpm: f64) {
+        let now = self.now();
+        self.state().tempo.set_bpm(now, bpm);
+    }
+
+    /// Sets the tempo to the average interval of the latest taps
+    pub fn tap(&self) {
+        let now = self.now();
+        self.state().tempo.tap(now);
+    }
+
+    /// Speeds up for positive and slows down for negative `direction` until called with `0`
+    pub fn nudge(&self, direction: i32) {
+        let now = self.now();
+        self.state().tempo.nudge(now, direction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Transport at 120 bpm, so every half second is one beat
+    fn transport() -> (Arc<MockClock>, Transport) {
+        let clock = Arc::new(MockClock::default());
+        let transport = Transport::new(clock.clone(), Tempo::default());
+        transport.set_loop_region(0.0, 8.0);
+        (clock, transport)
+    }
+
+    fn beats(beats: u64) -> Duration {
+        Duration::from_millis(500 * beats)
+    }
+
+    #[test]
+    fn play_pause_and_stop() {
+        let (clock, transport) = transport();
+        clock.advance(beats(1));
+        assert_eq!(transport.position(), 0.0);
+
+        transport.play();
+        clock.advance(beats(3));
+        assert_eq!(transport.position(), 3.0);
+
+        transport.pause();
+        clock.advance(beats(2));
+        assert!(!transport.is_playing());
+        assert_eq!(transport.position(), 3.0);
+
+        // Continues where it was paused
+        transport.play();
+        clock.advance(beats(1));
+        assert_eq!(transport.position(), 4.0);
+
+        transport.stop();
+        clock.advance(beats(1));
+        assert!(!transport.is_playing());
+        assert_eq!(transport.position(), 0.0);
+    }
+
+    #[test]
+    fn seek_while_playing() {
+        let (clock, transport) = transport();
+        transport.play();
+        clock.advance(beats(2));
+        transport.seek(6.0);
+        assert_eq!(transport.position(), 6.0);
+        clock.advance(beats(1));
+        assert!(transport.is_playing());
+        assert_eq!(transport.position(), 7.0);
+    }
+
+    #[test]
+    fn wraps_at_the_loop_end() {
+        let (clock, transport) = transport();
+        transport.set_loop_region(2.0, 6.0);
+        transport.play();
+        clock.advance(beats(5));
+        assert_eq!(transport.position(), 5.0);
+        // The playhead started before the region and loops inside it
+        clock.advance(beats(2));
+        assert_eq!(transport.position(), 3.0);
+        clock.advance(beats(4));
+        assert_eq!(transport.position(), 3.0);
+
+        transport.restart();
+        assert_eq!(transport.position(), 2.0);
+    }
+
+    #[test]
+    fn seeking_past_the_loop_end_while_paused() {
+        let (clock, transport) = transport();
+        transport.set_loop_region(2.0, 4.0);
+        transport.seek(7.5);
+        assert_eq!(transport.position(), 3.5);
+        // Before the region the playhead stays where it is
+        transport.seek(1.0);
+        assert_eq!(transport.position(), 1.0);
+
+        transport.seek(7.5);
+        transport.play();
+        clock.advance(beats(1));
+        assert_eq!(transport.position(), 2.5);
+    }
+
+    #[test]
+    fn changing_the_loop_region_keeps_the_playhead() {
+        let (clock, transport) = transport();
+        transport.play();
+        clock.advance(beats(10));
+        assert_eq!(transport.position(), 2.0);
+
+        transport.set_loop_region(1.0, 4.0);
+        assert_eq!(transport.position(), 2.0);
+        clock.advance(beats(3));
+        assert_eq!(transport.position(), 2.0);
+
+        // Empty regions are ignored
+        transport.set_loop_region(3.0, 3.0);
+        assert_eq!(transport.loop_region(), (1.0, 4.0));
+    }
+
+    #[test]
+    fn tempo_changes_keep_the_playhead() {
+        let (clock, transport) = transport();
+        transport.play();
+        clock.advance(beats(2));
+        transport.set_bpm(60.0);
+        assert_eq!(transport.position(), 2.0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(transport.position(), 3.0);
+    }
+}
@@ -0,0 +1,526 @@
+use super::Transport;
+use std::collections::VecDeque;
+
+/// Clock pulses per quarter note
+pub const PPQN: u32 = 24;
+/// Pulses the tempo is averaged over
+const TEMPO_PULSES: usize = PPQN as usize;
+/// Pulses further apart start a new tempo measurement, e.g. after the sender was paused
+const PULSE_TIMEOUT: f64 = 0.5;
+
+const SONG_POSITION: u8 = 0xf2;
+const CLOCK: u8 = 0xf8;
+const START: u8 = 0xfa;
+const CONTINUE: u8 = 0xfb;
+const STOP: u8 = 0xfc;
+
+/// The MIDI messages a clock follower cares about
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Clock,
+    /// Play from the beginning of the song
+    Start,
+    /// Play from the song position
+    Continue,
+    Stop,
+    /// Position in sixteenth notes
+    SongPosition(u16),
+}
+
+/// Picks the clock messages out of a raw MIDI byte stream
+///
+/// Real time messages may appear between the bytes of other messages, everything else is skipped.
+#[derive(Clone, Debug, Default)]
+pub struct Parser {
+    status: Option<u8>,
+    data: Vec<u8>,
+}
+
+impl Parser {
+    pub fn push(&mut self, byte: u8) -> Option<Message> {
+        match byte {
+            CLOCK => Some(Message::Clock),
+            START => Some(Message::Start),
+            CONTINUE => Some(Message::Continue),
+            STOP => Some(Message::Stop),
+            // Other real time messages don't interrupt anything
+            0xf8..=0xff => None,
+            0x80..=0xf7 => {
+                self.status = Some(byte).filter(|status| *status != 0xf7);
+                self.data.clear();
+                None
+            }
+            _ => match self.status {
+                Some(SONG_POSITION) => {
+                    self.data.push(byte);
+                    if self.data.len() < 2 {
+                        return None;
+                    }
+                    // System common messages have no running status
+                    self.status = None;
+                    let position = self.data[0] as u16 | (self.data[1] as u16) << 7;
+                    Some(Message::SongPosition(position))
+                }
+                // Data of channel messages and system exclusive
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Follows the clock of another device with a transport
+///
+/// The tempo is measured from the time between clock pulses, the position is set on every pulse
+/// and the transport plays on between them.
+#[derive(Clone, Debug, Default)]
+pub struct ClockSync {
+    /// Whether pulses move the playhead, set by start and continue
+    armed: bool,
+    /// Whether the first pulse since start or continue was received
+    running: bool,
+    /// Beat position of the latest song position pointer
+    start: f64,
+    /// Pulses since `start`
+    pulses: u64,
+    /// Times of the latest pulses in seconds, oldest first
+    times: VecDeque<f64>,
+}
+
+impl ClockSync {
+    /// Whether the other device is playing
+    pub fn is_running(&self) -> bool {
+        self.armed
+    }
+
+    /// Tempo measured from the latest pulses, also while the other device is stopped
+    pub fn bpm(&self) -> Option<f64> {
+        let (first, last) = (self.times.front()?, self.times.back()?);
+        let intervals = self.times.len() - 1;
+        (intervals > 0 && last > first)
+            .then(|| 60.0 * intervals as f64 / ((last - first) * PPQN as f64))
+    }
+
+    fn beat(&self) -> f64 {
+        self.start + self.pulses as f64 / PPQN as f64
+    }
+
+    pub fn handle(&mut self, message: Message, transport: &Transport) {
+        match message {
+            Message::Clock => {
+                let now = transport.now();
+                if self
+                    .times
+                    .back()
+                    .map_or(false, |last| now - last > PULSE_TIMEOUT || now < *last)
+                {
+                    self.times.clear();
+                }
+                self.times.push_back(now);
+                if self.times.len() > TEMPO_PULSES + 1 {
+                    self.times.pop_front();
+                }
+                if let Some(bpm) = self.bpm() {
+                    transport.set_bpm(bpm);
+                }
+
+                if !self.armed {
+                    return;
+                }
+                // The first pulse after start or continue is on the position itself
+                if self.running {
+                    self.pulses += 1;
+                }
+                transport.seek(self.beat());
+                if !self.running {
+                    self.running = true;
+                    transport.play();
+                }
+            }
+            Message::Start => {
+                self.start = 0.0;
+                self.pulses = 0;
+                self.arm(transport);
+            }
+            Message::Continue => self.arm(transport),
+            Message::Stop => {
+                self.armed = false;
+                self.running = false;
+                transport.pause();
+            }
+            Message::SongPosition(sixteenths) => {
+                self.start = sixteenths as f64 / 4.0;
+                self.pulses = 0;
+                // Senders only move the position while stopped, but better safe than sorry
+                self.running = false;
+                if !transport.is_playing() {
+                    transport.seek(self.start);
+                }
+            }
+        }
+    }
+
+    /// Playback starts with the next pulse
+    fn arm(&mut self, transport: &Transport) {
+        self.armed = true;
+        self.running = false;
+        transport.pause();
+        transport.seek(self.beat());
+    }
+}
+
+pub use self::input::{devices, MidiInput};
+
+mod input {
+    use super::{ClockSync, Transport};
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread::JoinHandle,
+    };
+
+    /// How long the reading thread waits for bytes before it checks whether it should stop
+    #[cfg(unix)]
+    const POLL_TIMEOUT_MS: i32 = 20;
+
+    /// Raw MIDI devices of ALSA and OSS, empty on other systems
+    ///
+    /// Virtual ports of the `snd-virmidi` module show up here too, other programs connect to them
+    /// with `aconnect`.
+    pub fn devices() -> Vec<String> {
+        let mut devices: Vec<String> = [("/dev/snd", "midiC"), ("/dev", "midi")]
+            .into_iter()
+            .filter_map(|(dir, prefix)| Some((std::fs::read_dir(dir).ok()?, prefix)))
+            .flat_map(|(entries, prefix)| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(move |path| {
+                        path.file_name()
+                            .and_then(|name| name.to_str())
+                            .map_or(false, |name| name.starts_with(prefix))
+                    })
+                    .map(|path| path.display().to_string())
+            })
+            .collect();
+        devices.sort();
+        devices
+    }
+
+    /// Reads the clock of a raw MIDI device on its own thread and drives a transport with it
+    ///
+    /// Dropping it stops the thread and closes the device, so it can be opened again right away.
+    pub struct MidiInput {
+        path: String,
+        stop: Arc<AtomicBool>,
+        sync: Arc<Mutex<ClockSync>>,
+        error: Arc<Mutex<Option<String>>>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl MidiInput {
+        /// Only raw MIDI devices of unix systems are supported
+        #[cfg(unix)]
+        pub fn open(path: &str, transport: Transport) -> io::Result<Self> {
+            use super::Parser;
+            use std::{fs::OpenOptions, io::Read, os::unix::fs::OpenOptionsExt};
+
+            // Not blocking, so the thread can notice that it should stop while nothing is sent
+            let mut file = OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path)?;
+            let stop = Arc::new(AtomicBool::new(false));
+            let sync = Arc::new(Mutex::new(ClockSync::default()));
+            let error = Arc::new(Mutex::new(None));
+
+            let thread = {
+                let (stop, sync, error) = (stop.clone(), sync.clone(), error.clone());
+                std::thread::Builder::new()
+                    .name("midi clock".to_owned())
+                    .spawn(move || {
+                        let mut parser = Parser::default();
+                        let mut buffer = [0; 64];
+                        while !stop.load(Ordering::Relaxed) {
+                            let read = match wait_readable(&file) {
+                                Ok(true) => match file.read(&mut buffer) {
+                                    Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+                                    result => result,
+                                },
+                                Ok(false) => continue,
+                                Err(err) => Err(err),
+                            };
+                            let read = match read {
+                                Ok(read) => read,
+                                Err(err)
+                                    if matches!(
+                                        err.kind(),
+                                        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                                    ) =>
+                                {
+                                    continue
+                                }
+                                Err(err) => {
+                                    log::warn!("Could not read MIDI clock: {err}");
+                                    *error.lock().expect("MIDI error poisoned") =
+                                        Some(err.to_string());
+                                    break;
+                                }
+                            };
+                            // Bytes arriving while disconnecting must not move the playhead
+                            if stop.load(Ordering::Relaxed) {
+                                break;
+                            }
+                            let mut sync = sync.lock().expect("MIDI clock sync poisoned");
+                            for message in
+                                buffer[..read].iter().filter_map(|byte| parser.push(*byte))
+                            {
+                                sync.handle(message, &transport);
+                            }
+                        }
+                    })?
+            };
+
+            Ok(Self {
+                path: path.to_owned(),
+                stop,
+                sync,
+                error,
+                thread: Some(thread),
+            })
+        }
+
+        #[cfg(not(unix))]
+        pub fn open(_path: &str, _transport: Transport) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Raw MIDI devices are only supported on unix systems",
+            ))
+        }
+
+        pub fn path(&self) -> &str {
+            &self.path
+        }
+
+        pub fn is_connected(&self) -> bool {
+            self.thread
+                .as_ref()
+                .map_or(false, |thread| !thread.is_finished())
+        }
+
+        /// Why reading stopped
+        pub fn error(&self) -> Option<String> {
+            self.error.lock().expect("MIDI error poisoned").clone()
+        }
+
+        pub fn sync(&self) -> ClockSync {
+            self.sync.lock().expect("MIDI clock sync poisoned").clone()
+        }
+    }
+
+    /// Whether `file` has bytes to read, waits at most `POLL_TIMEOUT_MS`
+    #[cfg(unix)]
+    fn wait_readable(file: &std::fs::File) -> io::Result<bool> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut poll_fd = libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `poll_fd` is one valid pollfd and outlives the call
+        match unsafe { libc::poll(&mut poll_fd, 1, POLL_TIMEOUT_MS) } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Ok(false),
+            // Errors and hang ups are reported by reading
+            _ => Ok(true),
+        }
+    }
+
+    impl Drop for MidiInput {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            // The device is closed when the thread ends, at most a poll timeout later
+            if let Some(thread) = self.thread.take() {
+                if thread.join().is_err() {
+                    log::warn!("MIDI clock thread panicked");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{tempo::Tempo, MockClock};
+    use std::{sync::Arc, time::Duration};
+
+    /// Time between pulses at 125 bpm
+    const PULSE: Duration = Duration::from_millis(20);
+
+    /// Parser, clock follower and a transport on a mock clock, like a connected device
+    struct Device {
+        clock: Arc<MockClock>,
+        transport: Transport,
+        parser: Parser,
+        sync: ClockSync,
+    }
+
+    impl Device {
+        fn new() -> Self {
+            let clock = Arc::new(MockClock::default());
+            let transport = Transport::new(clock.clone(), Tempo::default());
+            transport.set_loop_region(0.0, 64.0);
+            Self {
+                clock,
+                transport,
+                parser: Parser::default(),
+                sync: ClockSync::default(),
+            }
+        }
+
+        /// Replays recorded bytes, each chunk after the time waited before it
+        fn replay(&mut self, stream: &[(Duration, &[u8])]) {
+            for (wait, bytes) in stream {
+                self.clock.advance(*wait);
+                for byte in *bytes {
+                    if let Some(message) = self.parser.push(*byte) {
+                        self.sync.handle(message, &self.transport);
+                    }
+                }
+            }
+        }
+
+        fn pulses(&mut self, count: usize) {
+            let stream = vec![(PULSE, [CLOCK].as_slice()); count];
+            self.replay(&stream);
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> Vec<Message> {
+        let mut parser = Parser::default();
+        bytes.iter().filter_map(|byte| parser.push(*byte)).collect()
+    }
+
+    #[test]
+    fn real_time_bytes_between_song_position_data() {
+        assert_eq!(
+            parse(&[SONG_POSITION, 0x10, CLOCK, 0x01, START]),
+            vec![
+                Message::Clock,
+                Message::SongPosition(0x10 | 0x01 << 7),
+                Message::Start
+            ]
+        );
+        // Active sensing is skipped without breaking the message either
+        assert_eq!(
+            parse(&[SONG_POSITION, 0x7f, 0xfe, 0x7f]),
+            vec![Message::SongPosition(0x3fff)]
+        );
+    }
+
+    #[test]
+    fn other_messages_are_skipped() {
+        // Notes with running status, system exclusive and a stray data byte after the position
+        assert_eq!(
+            parse(&[
+                0x90,
+                0x3c,
+                0x7f,
+                0x3e,
+                0x7f,
+                0xf0,
+                0x7e,
+                0x72,
+                0xf7,
+                SONG_POSITION,
+                0x04,
+                0x00,
+                0x05,
+                STOP
+            ]),
+            vec![Message::SongPosition(4), Message::Stop]
+        );
+    }
+
+    #[test]
+    fn tempo_from_pulse_spacing() {
+        let mut device = Device::new();
+        assert_eq!(device.sync.bpm(), None);
+        device.pulses(1 + PPQN as usize);
+        let bpm = device.sync.bpm().unwrap();
+        assert!((bpm - 125.0).abs() < 1e-6, "{bpm}");
+        assert!((device.transport.bpm() - 125.0).abs() < 1e-6);
+        // Pulses alone don't start playback
+        assert!(!device.transport.is_playing());
+
+        // A long pause starts a new measurement
+        device.replay(&[(Duration::from_secs(1), &[CLOCK])]);
+        assert_eq!(device.sync.bpm(), None);
+        device.replay(&[(Duration::from_millis(10), &[CLOCK])]);
+        assert!((device.sync.bpm().unwrap() - 250.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn song_position_then_continue() {
+        let mut device = Device::new();
+        // Sixteen sixteenth notes are four beats
+        device.replay(&[(PULSE, &[SONG_POSITION, 16, 0])]);
+        assert_eq!(device.transport.position(), 4.0);
+
+        device.replay(&[(PULSE, &[CONTINUE])]);
+        assert!(device.sync.is_running());
+        assert!(!device.transport.is_playing());
+        // Playback starts on the first pulse, which is on the position itself
+        device.pulses(1);
+        assert!(device.transport.is_playing());
+        assert_eq!(device.transport.position(), 4.0);
+        device.pulses(PPQN as usize);
+        assert!((device.transport.position() - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn song_position_past_the_loop_region() {
+        let mut device = Device::new();
+        device.transport.set_loop_region(0.0, 4.0);
+        // Ten beats into the song are two beats into a loop of four
+        device.replay(&[(PULSE, &[SONG_POSITION, 40, 0])]);
+        assert_eq!(device.transport.position(), 2.0);
+
+        device.replay(&[(PULSE, &[CONTINUE])]);
+        assert_eq!(device.transport.position(), 2.0);
+        device.pulses(1 + 3 * PPQN as usize);
+        assert!((device.transport.position() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn start_plays_from_the_beginning() {
+        let mut device = Device::new();
+        device.replay(&[(PULSE, &[SONG_POSITION, 16, 0])]);
+        device.replay(&[(PULSE, &[START])]);
+        device.pulses(1 + 2 * PPQN as usize);
+        assert!((device.transport.position() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stop_pauses_the_transport() {
+        let mut device = Device::new();
+        device.replay(&[(PULSE, &[START])]);
+        device.pulses(1 + PPQN as usize);
+        device.replay(&[(PULSE / 2, &[STOP])]);
+        assert!(!device.transport.is_playing());
+        assert!(!device.sync.is_running());
+        let position = device.transport.position();
+        assert!(
+            (position - (1.0 + 0.5 / PPQN as f64)).abs() < 1e-6,
+            "{position}"
+        );
+
+        // Clocks sent while stopped keep the position
+        device.pulses(PPQN as usize);
+        assert_eq!(device.transport.position(), position);
+    }
+}
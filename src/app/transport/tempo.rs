@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+pub const MIN_BPM: f64 = 20.0;
+pub const MAX_BPM: f64 = 300.0;
+/// Relative speed change while nudging
+pub const NUDGE: f64 = 0.05;
+/// A longer pause starts a new series of taps
+const TAP_TIMEOUT: f64 = 2.0;
+/// Only the latest taps count, so the tempo follows changes
+const MAX_TAPS: usize = 8;
+
+/// Speed of the playhead
+///
+/// Times are in seconds of any clock that does not go backwards. The beat position is counted
+/// from an anchor which moves along whenever the speed changes, so changing the tempo never
+/// makes the playhead jump.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tempo {
+    bpm: f64,
+    /// Time and beat position the beat position is counted from
+    #[serde(skip)]
+    anchor: (f64, f64),
+    /// Times of the latest taps, oldest first
+    #[serde(skip)]
+    taps: Vec<f64>,
+    /// Relative speed change while nudging, `0.0` otherwise
+    #[serde(skip)]
+    nudge: f64,
+}
+
+impl Default for Tempo {
+    fn default() -> Self {
+        Self {
+            bpm: 120.0,
+            anchor: (0.0, 0.0),
+            taps: Vec::new(),
+            nudge: 0.0,
+        }
+    }
+}
+
+impl Tempo {
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Beats per second including nudging
+    fn speed(&self) -> f64 {
+        self.bpm / 60.0 * (1.0 + self.nudge)
+    }
+
+    /// Beat position at `now`
+    pub fn beat(&self, now: f64) -> f64 {
+        let (time, beat) = self.anchor;
+        beat + (now - time) * self.speed()
+    }
+
+    /// Continues counting from `beat` at `now`
+    pub fn set_beat(&mut self, now: f64, beat: f64) {
+        self.anchor = (now, beat);
+    }
+
+    pub fn set_bpm(&mut self, now: f64, bpm: f64) {
+        if bpm.is_finite() {
+            self.set_beat(now, self.beat(now));
+            self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+        }
+    }
+
+    /// Speeds up for positive and slows down for negative `direction` until called with `0`
+    pub fn nudge(&mut self, now: f64, direction: i32) {
+        let nudge = NUDGE * direction.signum() as f64;
+        if nudge != self.nudge {
+            self.set_beat(now, self.beat(now));
+            self.nudge = nudge;
+        }
+    }
+
+    /// Sets the tempo to the average interval of the latest taps
+    ///
+    /// Every tap after the first also moves the nearest beat to the tap.
+    pub fn tap(&mut self, now: f64) {
+        if self
+            .taps
+            .last()
+            .map_or(true, |last| now - last > TAP_TIMEOUT || now < *last)
+        {
+            self.taps.clear();
+        }
+        self.taps.push(now);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+
+        if let (Some(first), Some(last)) = (self.taps.first(), self.taps.last()) {
+            let intervals = self.taps.len() - 1;
+            if intervals > 0 && last > first {
+                self.set_bpm(now, 60.0 * intervals as f64 / (last - first));
+                let beat = self.beat(now).round();
+                self.set_beat(now, beat);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tempo after tapping at all `times`
+    fn tapped(times: &[f64]) -> Tempo {
+        let mut tempo = Tempo::default();
+        for time in times {
+            tempo.tap(*time);
+        }
+        tempo
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{actual} instead of {expected}"
+        );
+    }
+
+    #[test]
+    fn taps_set_the_average_tempo() {
+        assert_close(tapped(&[10.0, 10.5, 11.0, 11.5]).bpm(), 120.0);
+        // Uneven taps are averaged over the whole series
+        assert_close(tapped(&[10.0, 10.4, 11.0, 12.0]).bpm(), 90.0);
+        // A single tap keeps the tempo
+        assert_close(tapped(&[10.0]).bpm(), 120.0);
+    }
+
+    #[test]
+    fn pauses_start_a_new_series() {
+        let mut tempo = tapped(&[0.0, 0.5, 1.0]);
+        tempo.tap(1.0 + TAP_TIMEOUT + 0.1);
+        assert_close(tempo.bpm(), 120.0);
+        tempo.tap(1.0 + TAP_TIMEOUT + 0.85);
+        assert_close(tempo.bpm(), 80.0);
+
+        // So does a clock going backwards
+        tempo.tap(0.0);
+        tempo.tap(1.0);
+        assert_close(tempo.bpm(), 60.0);
+    }
+
+    #[test]
+    fn only_the_latest_taps_count() {
+        let mut times: Vec<f64> = (0..MAX_TAPS).map(|i| i as f64).collect();
+        let mut tempo = tapped(&times);
+        assert_close(tempo.bpm(), 60.0);
+
+        // The first tap is dropped, its interval of a second does not count any more
+        let last = times[MAX_TAPS - 1] + 0.5;
+        tempo.tap(last);
+        times.push(last);
+        let intervals = (MAX_TAPS - 1) as f64;
+        assert_close(tempo.bpm(), 60.0 * intervals / (last - times[1]));
+    }
+
+    #[test]
+    fn taps_move_the_nearest_beat() {
+        let mut tempo = Tempo::default();
+        tempo.set_beat(0.0, 0.3);
+        // The first tap only starts the series
+        tempo.tap(1.0);
+        assert_close(tempo.beat(1.0), 2.3);
+        tempo.tap(1.5);
+        assert_close(tempo.beat(1.5), 3.0);
+        assert_close(tempo.beat(2.0), 4.0);
+    }
+
+    #[test]
+    fn nudging_changes_the_speed_without_jumps() {
+        let mut tempo = Tempo::default();
+        tempo.nudge(1.0, 3);
+        assert_close(tempo.beat(1.0), 2.0);
+        assert_close(tempo.beat(2.0), 2.0 + 2.0 * (1.0 + NUDGE));
+
+        // Holding the nudge keeps the anchor where it is
+        tempo.nudge(1.5, 1);
+        assert_close(tempo.beat(2.0), 2.0 + 2.0 * (1.0 + NUDGE));
+
+        tempo.nudge(2.0, -1);
+        assert_close(tempo.beat(2.0), 2.0 + 2.0 * (1.0 + NUDGE));
+        assert_close(tempo.beat(3.0), 4.1 + 2.0 * (1.0 - NUDGE));
+
+        tempo.nudge(3.0, 0);
+        assert_close(tempo.beat(4.0), 4.1 + 1.9 + 2.0);
+        assert_close(tempo.bpm(), 120.0);
+    }
+
+    #[test]
+    fn tempo_stays_in_range() {
+        let mut tempo = Tempo::default();
+        tempo.set_bpm(0.0, 1000.0);
+        assert_close(tempo.bpm(), MAX_BPM);
+        tempo.set_bpm(0.0, 1.0);
+        assert_close(tempo.bpm(), MIN_BPM);
+        tempo.set_bpm(0.0, f64::NAN);
+        assert_close(tempo.bpm(), MIN_BPM);
+        // Taps far too fast for the range
+        assert_close(tapped(&[0.0, 0.01]).bpm(), MAX_BPM);
+    }
+}